@@ -1,21 +1,38 @@
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::parse_quote;
 use syn::Type;
 use syn::{
     parenthesized,
     parse::{Parse, ParseBuffer, ParseStream},
-    parse_macro_input, AttrStyle, Attribute, Data, DeriveInput, Expr, Ident, LitStr, Result, Token,
+    parse_macro_input, AttrStyle, Attribute, Data, DeriveInput, Expr, Field, Fields, Generics,
+    Ident, LitStr, Member, Result, Token,
 };
 
+mod query;
+
 #[derive(Default)]
 struct SqlArgs {
     name: Option<String>,
     foreign_key: Option<(Expr, Option<&'static str>, Option<&'static str>)>,
     unique: bool,
+    primary_key: bool,
     conversion: Option<(Type, Expr, Expr)>,
+    view_of: Option<Type>,
+    column: Option<Ident>,
+    timestamp: Option<Ident>,
+    version: bool,
+    fulltext: bool,
+    belongs_to: Option<Ident>,
+    has_many: Option<Ident>,
+    rename: Option<String>,
+    integer: bool,
+    native_enum: Option<Option<String>>,
+    flatten: bool,
+    as_str: bool,
+    insertable: bool,
 }
 
 impl FromIterator<SqlArgs> for SqlArgs {
@@ -25,7 +42,21 @@ impl FromIterator<SqlArgs> for SqlArgs {
                 name: next.name.or(acc.name),
                 foreign_key: next.foreign_key.or(acc.foreign_key),
                 unique: acc.unique || next.unique,
+                primary_key: acc.primary_key || next.primary_key,
                 conversion: next.conversion.or(acc.conversion),
+                view_of: next.view_of.or(acc.view_of),
+                column: next.column.or(acc.column),
+                timestamp: next.timestamp.or(acc.timestamp),
+                version: acc.version || next.version,
+                fulltext: acc.fulltext || next.fulltext,
+                belongs_to: next.belongs_to.or(acc.belongs_to),
+                has_many: next.has_many.or(acc.has_many),
+                rename: next.rename.or(acc.rename),
+                integer: acc.integer || next.integer,
+                native_enum: next.native_enum.or(acc.native_enum),
+                flatten: acc.flatten || next.flatten,
+                as_str: acc.as_str || next.as_str,
+                insertable: acc.insertable || next.insertable,
             })
             .unwrap_or_default()
     }
@@ -117,8 +148,46 @@ impl Parse for SqlArgs {
                 "name" => this.name(&input)?,
                 "references" => this.references(&input)?,
                 "unique" => this.unique = true,
+                "primary_key" => this.primary_key = true,
                 "db_as" => this.conversion(&input)?,
+                "view_of" => {
+                    input.parse::<Token![=]>()?;
+                    this.view_of = Some(input.parse::<Type>()?);
+                }
+                "column" => {
+                    input.parse::<Token![=]>()?;
+                    this.column = Some(input.parse::<Ident>()?);
+                }
+                "version" => this.version = true,
+                "fulltext" => this.fulltext = true,
+                "rename" => {
+                    input.parse::<Token![=]>()?;
+                    this.rename = Some(input.parse::<LitStr>()?.value());
+                }
+                "integer" => this.integer = true,
+                "flatten" => this.flatten = true,
+                "insertable" => this.insertable = true,
+                "native_enum" => {
+                    this.native_enum = Some(if input.peek(Token![=]) {
+                        input.parse::<Token![=]>()?;
+                        Some(input.parse::<LitStr>()?.value())
+                    } else {
+                        None
+                    })
+                }
+                "belongs_to" => {
+                    input.parse::<Token![=]>()?;
+                    this.belongs_to = Some(input.parse::<Ident>()?);
+                }
+                "has_many" => {
+                    input.parse::<Token![=]>()?;
+                    this.has_many = Some(input.parse::<Ident>()?);
+                }
+                "created_at" => this.timestamp = Some(format_ident!("CreatedAt")),
+                "updated_at" => this.timestamp = Some(format_ident!("UpdatedAt")),
+                "soft_delete" => this.timestamp = Some(format_ident!("SoftDelete")),
                 "as_str" => {
+                    this.as_str = true;
                     this.conversion = Some((
                         parse_quote!(::std::string::String),
                         parse_quote!(|x| ::std::string::ToString::to_string(&x)),
@@ -154,96 +223,767 @@ fn table_impl(input: DeriveInput) -> Result<TokenStream> {
             "#[derive(Table)] only applies to structs",
         ));
     };
-    assert!(
-        input.generics.type_params().count() == 0
-            && input.generics.const_params().count() == 0
-            && input.generics.lifetimes().count() == 0
-    );
     let sql_args = sql_attrs(&input.attrs)?;
     let name = input.ident;
     let table_name = sql_args.name.unwrap_or_else(|| name.to_string());
 
-    let field_name = data
+    let field_name = field_idents(&data.fields);
+    let member = data.fields.iter().enumerate().map(|(i, c)| member(i, c)).collect::<Vec<_>>();
+    let flatten = data
         .fields
         .iter()
-        .map(|c| c.ident.as_ref().unwrap())
-        .collect::<Vec<_>>();
-    let column_type = data.fields.iter().map(|c| &c.ty).collect::<Vec<_>>();
-    let db_type = data
+        .map(|c| Ok(sql_attrs(&c.attrs)?.flatten))
+        .collect::<Result<Vec<_>>>()?;
+    let (primary_key, primary_key_value): (Vec<_>, Vec<_>) = data
         .fields
         .iter()
-        .map(|c| {
-            Ok(sql_attrs(&c.attrs)?
-                .conversion
-                .as_ref()
-                .map(|t| &t.0)
-                .unwrap_or(&c.ty)
-                .clone())
+        .zip(field_name.iter().zip(&member))
+        .map(|(c, (field_name, member))| {
+            let attrs = sql_attrs(&c.attrs)?;
+            if attrs.flatten && attrs.primary_key {
+                return Err(syn::Error::new_spanned(c, "Flattened fields can't be primary keys"));
+            }
+            Ok(attrs
+                .primary_key
+                .then(|| (field_name, field_db_value(c, &attrs, quote!(self.#member)))))
         })
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let db_type = db_types(&data.fields)?
+        .into_iter()
+        .zip(&flatten)
+        .filter(|(_, &flatten)| !flatten)
+        .map(|(ty, _)| ty)
+        .collect::<Vec<_>>();
+
+    let generics = bounded_generics(&input.generics, &db_type)?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    // Fields converted with `db_as` are cloned to be written, so tables with them only implement `Insertable` when
+    // asked to with `#[sql(insertable)]`
+    let cloned = data
+        .fields
+        .iter()
+        .map(|c| Ok(needs_clone(&sql_attrs(&c.attrs)?).then_some(&c.ty)))
+        .filter_map(Result::transpose)
         .collect::<Result<Vec<_>>>()?;
+    let insertable = sql_args.insertable || cloned.is_empty();
+    let mut insertable_generics = generics.clone();
+    if generics.type_params().next().is_some() {
+        let where_clause = insertable_generics.make_where_clause();
+        for ty in &cloned {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::core::clone::Clone));
+        }
+    }
+    let (_, _, insertable_where_clause) = insertable_generics.split_for_impl();
 
-    let columns = data
+    let mut prefixes = vec![];
+    let (column_ty, column_value): (Vec<_>, Vec<_>) = data
         .fields
         .iter()
-        .map(|c| {
+        .zip(&field_name)
+        .map(|(c, field_name)| {
+            let attrs = sql_attrs(&c.attrs)?;
+            if !attrs.flatten {
+                return column(c, attrs, field_name, &quote!(Self));
+            }
+            let (ty, prefix) = (&c.ty, flatten_prefix(&name, field_name));
+            let prefix_name = attrs.name.unwrap_or_else(|| format!("{}_", field_name));
+            prefixes.push(quote! {
+                #[doc(hidden)]
+                #[allow(non_camel_case_types)]
+                pub struct #prefix;
+
+                #[automatically_derived]
+                impl ::sql::Prefix for #prefix {
+                    const PREFIX: &'static str = #prefix_name;
+                }
+            });
+            Ok((
+                quote!(<#ty as ::sql::Embeddable<Self, #prefix>>::Columns),
+                quote!(<#ty as ::sql::Embeddable<Self, #prefix>>::COLUMNS),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let (mut out_ty, mut from_value, mut insert_value) = (vec![], vec![], vec![]);
+    for (c, (field_name, member)) in data.fields.iter().zip(field_name.iter().zip(&member)) {
+        let ty = &c.ty;
+        let attrs = sql_attrs(&c.attrs)?;
+        if attrs.flatten {
+            let prefix = flatten_prefix(&name, field_name);
+            out_ty.push(quote! {
+                <<#ty as ::sql::Embeddable<#name #ty_generics, #prefix>>::Columns
+                    as ::sql::ColumnTuple<#name #ty_generics>>::Out
+            });
+            from_value.push(quote!(::core::convert::From::from(#field_name)));
+            insert_value.push(quote!(values.extend(<#ty as ::sql::Embeddable<Self, #prefix>>::values(&self.#member))));
+        } else {
+            out_ty.push(quote!(#ty));
+            from_value.push(quote!(#field_name));
+            let value = field_db_value(c, &attrs, quote!(self.#member));
+            insert_value.push(quote!(values.push(#value)));
+        }
+    }
+    let columns_ty = nested_tuple(column_ty.iter().cloned());
+    let columns = nested_tuple(field_name.iter().map(|f| quote!(Self::#f)));
+    let out_ty = nested_tuple(out_ty);
+    let pattern = nested_tuple(field_name.iter().map(|f| quote!(#f)));
+    let insertable_impl = if insertable {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::sql::Insertable for #name #ty_generics #insertable_where_clause {
+                fn values(&self) -> ::std::vec::Vec<::sql::DbValue> {
+                    let mut values = ::std::vec::Vec::with_capacity(
+                        <<Self as ::sql::Table>::Columns as ::sql::ColumnTuple<Self>>::N,
+                    );
+                    #(#insert_value;)*
+                    values
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+    let primary_key_impl = if primary_key.is_empty() || !insertable {
+        quote!()
+    } else {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::sql::PrimaryKey for #name #ty_generics #insertable_where_clause {
+                fn primary_key(&self) -> ::sql::CondExpr<Self> {
+                    ::sql::CondExpr::all([
+                        #(Self::#primary_key.equals_db(#primary_key_value),)*
+                    ])
+                }
+            }
+        }
+    };
+    let relations = data
+        .fields
+        .iter()
+        .zip(field_name.iter().zip(&member))
+        .map(|(c, (field_name, member))| {
             let attrs = sql_attrs(&c.attrs)?;
-            let quote_option = |o| {
-                if let Some(s) = o {
-                    quote!(::core::option::Option::Some(#s))
-                } else {
-                    quote!(::core::option::Option::None)
+            if attrs.belongs_to.is_none() && attrs.has_many.is_none() {
+                return Ok(quote!());
+            }
+            let (foreign_key, _, _) = match &attrs.foreign_key {
+                Some(foreign_key) => foreign_key,
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        c,
+                        "belongs_to and has_many require #[sql(references(...))]",
+                    ))
                 }
             };
-            let foreign_key = attrs
-                .foreign_key
-                .map(|(e, on_update, on_delete)| {
-                    let on_update = quote_option(on_update);
-                    let on_delete = quote_option(on_delete);
-                    quote!(::core::option::Option::Some((&#e, #on_update, #on_delete)))
-                })
-                .unwrap_or(quote!(::core::option::Option::None));
-            let field_name = c.ident.as_ref().unwrap();
-            let column_type = &c.ty;
-            let column_name = attrs.name.unwrap_or_else(|| field_name.to_string());
-            let unique = quote_bool(attrs.unique);
-            let (ty, conversion) = attrs
-                .conversion
-                .as_ref()
-                .map(|(ty, to, from)| (ty, quote!((#to, #from))))
-                .unwrap_or((&c.ty, quote!((|x| x, ::core::result::Result::Ok))));
-            Ok(quote! {
-                const #field_name: ::sql::Column<Self, #ty, #column_type> = ::sql::Column::new(
-                    #column_name,
-                    #foreign_key,
-                    #unique,
-                    #conversion
-                );
-            })
+            let (parent, referenced) = match foreign_key {
+                Expr::Path(p) if p.qself.is_none() && p.path.segments.len() > 1 => {
+                    let segments = &p.path.segments;
+                    let parent = syn::Path {
+                        leading_colon: p.path.leading_colon,
+                        segments: segments.iter().take(segments.len() - 1).cloned().collect(),
+                    };
+                    let parent = if parent.is_ident("Self") {
+                        quote!(#name #ty_generics)
+                    } else {
+                        quote!(#parent)
+                    };
+                    (parent, &segments.last().unwrap().ident)
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        foreign_key,
+                        "belongs_to and has_many require a reference of the form Table::column",
+                    ))
+                }
+            };
+            let value = field_db_value(c, &attrs, quote!(self.#member));
+            // The referenced column has the same Rust type
+            let referenced_value = field_db_value(c, &attrs, quote!(self.#referenced));
+            let belongs_to = attrs.belongs_to.map(|belongs_to| {
+                quote! {
+                    #[automatically_derived]
+                    impl #impl_generics #name #ty_generics #insertable_where_clause {
+                        pub async fn #belongs_to<Db: ::sql::Database>(
+                            &self,
+                            pool: &::sql::Pool<Db>,
+                        ) -> ::sql::Result<::core::option::Option<#parent>> {
+                            ::core::result::Result::Ok(pool
+                                .select(<#parent as ::sql::Table>::COLUMNS)
+                                .r#where(<#parent>::#referenced.equals_db(#value))
+                                .limit(1)
+                                .fetch_all::<#parent>()
+                                .await?
+                                .pop())
+                        }
+                    }
+                }
+            });
+            let has_many = match attrs.has_many {
+                Some(_) if generics.type_params().next().is_some() => {
+                    return Err(syn::Error::new_spanned(
+                        c,
+                        "has_many isn't supported on generic tables",
+                    ))
+                }
+                Some(has_many) => quote! {
+                    #[automatically_derived]
+                    impl #parent {
+                        pub async fn #has_many<Db: ::sql::Database>(
+                            &self,
+                            pool: &::sql::Pool<Db>,
+                        ) -> ::sql::Result<::std::vec::Vec<#name>> {
+                            pool.select(<#name as ::sql::Table>::COLUMNS)
+                                .r#where(#name::#field_name.equals_db(#referenced_value))
+                                .fetch_all::<#name>()
+                                .await
+                        }
+                    }
+                },
+                None => quote!(),
+            };
+            Ok(quote!(#belongs_to #has_many))
         })
         .collect::<Result<TokenStream2>>()?;
     Ok(quote! {
+        #(#prefixes)*
+
         #[allow(non_upper_case_globals)]
         #[automatically_derived]
-        impl #name {
-            #columns
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(const #field_name: #column_ty = #column_value;)*
         }
         #[automatically_derived]
-        impl ::sql::Table for #name {
+        impl #impl_generics ::sql::Table for #name #ty_generics #where_clause {
             const TABLE_NAME: &'static str = #table_name;
-            type Columns = (#(::sql::Column<Self, #db_type, #column_type>,)*);
-            const COLUMNS: Self::Columns = (#(Self::#field_name, )*);
+            type Columns = #columns_ty;
+            const COLUMNS: Self::Columns = #columns;
+        }
+
+        #[automatically_derived]
+        impl #impl_generics ::std::convert::From<#out_ty> for #name #ty_generics #where_clause {
+            fn from(#pattern: #out_ty) -> Self {
+                Self { #(#member: #from_value, )* }
+            }
+        }
+
+        #insertable_impl
+
+        #primary_key_impl
+
+        #relations
+    }
+    .into())
+}
+
+#[proc_macro_derive(Embeddable, attributes(sql))]
+pub fn embeddable(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    match embeddable_impl(input) {
+        Ok(s) => s,
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn embeddable_impl(input: DeriveInput) -> Result<TokenStream> {
+    let data = if let Data::Struct(s) = input.data {
+        s
+    } else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "#[derive(Embeddable)] only applies to structs",
+        ));
+    };
+    if let Some(param) = input.generics.params.first() {
+        return Err(syn::Error::new_spanned(param, "#[derive(Embeddable)] doesn't support generics"));
+    }
+    let name = input.ident;
+    let field_name = field_idents(&data.fields);
+    let member = data.fields.iter().enumerate().map(|(i, c)| member(i, c)).collect::<Vec<_>>();
+    let column_type = data.fields.iter().map(|c| &c.ty).collect::<Vec<_>>();
+    let names = format_ident!("__sql_names_{}", name);
+    let mut column_name = vec![];
+    let (column_ty, column_value): (Vec<_>, Vec<_>) = data
+        .fields
+        .iter()
+        .zip(&field_name)
+        .map(|(c, field_name)| {
+            let attrs = sql_attrs(&c.attrs)?;
+            if attrs.flatten {
+                return Err(syn::Error::new_spanned(c, "Embeddable groups can't be nested"));
+            }
+            column_name.push(attrs.name.clone().unwrap_or_else(|| field_name.to_string()));
+            column(c, attrs, field_name, &quote!(__T))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let columns_ty = nested_tuple(column_ty);
+    let columns = nested_tuple(
+        column_value
+            .iter()
+            .zip(&field_name)
+            .map(|(c, f)| quote!(#c.renamed(::sql::__name_str(&#names::<__P>::#f)))),
+    );
+    let values = data
+        .fields
+        .iter()
+        .zip(&member)
+        .map(|(c, m)| Ok(field_db_value(c, &sql_attrs(&c.attrs)?, quote!(self.#m))))
+        .collect::<Result<Vec<_>>>()?;
+    let out_ty = nested_tuple(column_type.iter().map(|t| quote!(#t)));
+    let pattern = nested_tuple(field_name.iter().map(|f| quote!(#f)));
+
+    Ok(quote! {
+        /// The prefixed column names, built at compile time
+        #[allow(non_camel_case_types)]
+        struct #names<__P>(::core::marker::PhantomData<__P>);
+
+        #[allow(non_upper_case_globals)]
+        impl<__P: ::sql::Prefix> #names<__P> {
+            #(const #field_name: ([u8; ::sql::MAX_PREFIXED_NAME], usize) =
+                ::sql::__concat_name(__P::PREFIX, #column_name);)*
         }
 
         #[automatically_derived]
-        impl ::std::convert::From<(#(#column_type, )*)> for #name {
-            fn from((#(#field_name,)*): (#(#column_type,)*)) -> Self {
-                Self { #(#field_name, )* }
+        impl<__T: ::sql::Table, __P: ::sql::Prefix> ::sql::Embeddable<__T, __P> for #name {
+            type Columns = #columns_ty;
+            const COLUMNS: Self::Columns = #columns;
+
+            fn values(&self) -> ::std::vec::Vec<::sql::DbValue> {
+                ::std::vec![#(#values,)*]
+            }
+        }
+
+        #[automatically_derived]
+        impl ::std::convert::From<#out_ty> for #name {
+            fn from(#pattern: #out_ty) -> Self {
+                Self { #(#member: #field_name, )* }
             }
         }
     }
     .into())
 }
 
+#[proc_macro_derive(FromRow, attributes(sql))]
+pub fn from_row(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    match from_row_impl(input) {
+        Ok(s) => s,
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn from_row_impl(input: DeriveInput) -> Result<TokenStream> {
+    let data = if let Data::Struct(s) = input.data {
+        s
+    } else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "#[derive(FromRow)] only applies to structs",
+        ));
+    };
+    let table = sql_attrs(&input.attrs)?.view_of.ok_or_else(|| {
+        syn::Error::new(
+            Span::call_site(),
+            "#[derive(FromRow)] requires #[sql(view_of = Table)]",
+        )
+    })?;
+    let name = input.ident;
+
+    let binding = field_idents(&data.fields);
+    let member = data.fields.iter().enumerate().map(|(i, c)| member(i, c)).collect::<Vec<_>>();
+    let column_type = data.fields.iter().map(|c| &c.ty).collect::<Vec<_>>();
+    let column = data
+        .fields
+        .iter()
+        .map(|c| {
+            sql_attrs(&c.attrs)?
+                .column
+                .or_else(|| c.ident.clone())
+                .ok_or_else(|| {
+                    syn::Error::new_spanned(c, "Tuple struct fields require #[sql(column = ...)]")
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let db_type = db_types(&data.fields)?;
+
+    let generics = bounded_generics(&input.generics, &db_type)?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let columns_ty = nested_tuple(
+        db_type
+            .iter()
+            .zip(&column_type)
+            .map(|(db_type, column_type)| quote!(::sql::Column<#table, #db_type, #column_type>)),
+    );
+    let columns = nested_tuple(column.iter().map(|c| quote!(<#table>::#c)));
+    let out_ty = nested_tuple(column_type.iter().map(|t| quote!(#t)));
+    let pattern = nested_tuple(binding.iter().map(|b| quote!(#b)));
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::sql::FromRow for #name #ty_generics #where_clause {
+            type Table = #table;
+            type Columns = #columns_ty;
+            const COLUMNS: Self::Columns = #columns;
+        }
+
+        #[automatically_derived]
+        impl #impl_generics ::std::convert::From<#out_ty> for #name #ty_generics #where_clause {
+            fn from(#pattern: #out_ty) -> Self {
+                Self { #(#member: #binding, )* }
+            }
+        }
+    }
+    .into())
+}
+
+#[proc_macro]
+pub fn sql(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as query::SqlInput);
+    match query::sql_impl(input) {
+        Ok(s) => s.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(DbEnum, attributes(sql))]
+pub fn db_enum(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    match db_enum_impl(input) {
+        Ok(s) => s,
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn db_enum_impl(input: DeriveInput) -> Result<TokenStream> {
+    let data = if let Data::Enum(e) = input.data {
+        e
+    } else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "#[derive(DbEnum)] only applies to enums",
+        ));
+    };
+    if let Some(param) = input.generics.params.first() {
+        return Err(syn::Error::new_spanned(param, "#[derive(DbEnum)] doesn't support generics"));
+    }
+    let sql_args = sql_attrs(&input.attrs)?;
+    let name = input.ident;
+    let variant = data
+        .variants
+        .iter()
+        .map(|v| match v.fields {
+            Fields::Unit => Ok(&v.ident),
+            _ => Err(syn::Error::new_spanned(v, "#[derive(DbEnum)] requires fieldless variants")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (from_db, to_db, db_type) = if sql_args.integer {
+        if sql_args.native_enum.is_some() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "integer and native_enum can't be combined",
+            ));
+        }
+        (
+            quote! {
+                let value = <i64 as ::sql::DbColumnType>::from_db(db_value)?;
+                #(if value == Self::#variant as i64 {
+                    return ::core::result::Result::Ok(Self::#variant);
+                })*
+                ::core::result::Result::Err(::sql::__anyhow!("Invalid value {} for {}", value, stringify!(#name)))
+            },
+            quote! {
+                ::sql::DbColumnType::to_db(&match self {
+                    #(Self::#variant => Self::#variant as i64,)*
+                })
+            },
+            quote!(<i64 as ::sql::DbColumnType>::db_type()),
+        )
+    } else {
+        let label = data
+            .variants
+            .iter()
+            .map(|v| Ok(sql_attrs(&v.attrs)?.rename.unwrap_or_else(|| v.ident.to_string())))
+            .collect::<Result<Vec<_>>>()?;
+        let db_type = match sql_args.native_enum {
+            Some(type_name) => {
+                let type_name = type_name.unwrap_or_else(|| name.to_string());
+                quote!(::sql::DbType::enumeration(#type_name, &[#(#label,)*]))
+            }
+            None => quote!(<::std::string::String as ::sql::DbColumnType>::db_type()),
+        };
+        (
+            quote! {
+                let value = <::std::string::String as ::sql::DbColumnType>::from_db(db_value)?;
+                match value.as_str() {
+                    #(#label => ::core::result::Result::Ok(Self::#variant),)*
+                    _ => ::core::result::Result::Err(
+                        ::sql::__anyhow!("Invalid value {:?} for {}", value, stringify!(#name))
+                    ),
+                }
+            },
+            quote! {
+                ::sql::DbColumnType::to_db(&::std::string::String::from(match self {
+                    #(Self::#variant => #label,)*
+                }))
+            },
+            db_type,
+        )
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl ::sql::DbColumnType for #name {
+            fn from_db(db_value: &::sql::DbValue) -> ::sql::Result<Self> {
+                #from_db
+            }
+
+            fn to_db(&self) -> ::sql::DbValue {
+                #to_db
+            }
+
+            fn db_type() -> ::sql::DbType {
+                #db_type
+            }
+        }
+    }
+    .into())
+}
+
+#[proc_macro_derive(DbColumnType)]
+pub fn db_column_type(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    match db_column_type_impl(input) {
+        Ok(s) => s,
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn db_column_type_impl(input: DeriveInput) -> Result<TokenStream> {
+    let inner = match input.data {
+        Data::Struct(s) => match s.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => fields.unnamed[0].ty.clone(),
+            _ => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "#[derive(DbColumnType)] requires a struct with a single unnamed field",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "#[derive(DbColumnType)] only applies to structs",
+            ))
+        }
+    };
+    let name = input.ident;
+    let generics = bounded_generics(&input.generics, std::slice::from_ref(&inner))?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::sql::DbColumnType for #name #ty_generics #where_clause {
+            fn from_db(db_value: &::sql::DbValue) -> ::sql::Result<Self> {
+                ::core::result::Result::Ok(Self(<#inner as ::sql::DbColumnType>::from_db(db_value)?))
+            }
+
+            fn to_db(&self) -> ::sql::DbValue {
+                <#inner as ::sql::DbColumnType>::to_db(&self.0)
+            }
+
+            fn db_type() -> ::sql::DbType {
+                <#inner as ::sql::DbColumnType>::db_type()
+            }
+        }
+    }
+    .into())
+}
+
+fn column(
+    c: &Field,
+    attrs: SqlArgs,
+    field_name: &Ident,
+    table: &TokenStream2,
+) -> Result<(TokenStream2, TokenStream2)> {
+    let quote_option = |o| {
+        if let Some(s) = o {
+            quote!(::core::option::Option::Some(#s))
+        } else {
+            quote!(::core::option::Option::None)
+        }
+    };
+    let foreign_key = attrs
+        .foreign_key
+        .map(|(e, on_update, on_delete)| {
+            let on_update = quote_option(on_update);
+            let on_delete = quote_option(on_delete);
+            quote!(::core::option::Option::Some((&#e, #on_update, #on_delete)))
+        })
+        .unwrap_or(quote!(::core::option::Option::None));
+    let column_type = &c.ty;
+    let column_name = attrs.name.unwrap_or_else(|| field_name.to_string());
+    let unique = quote_bool(attrs.unique);
+    let primary_key = quote_bool(attrs.primary_key);
+    let version = quote_bool(attrs.version);
+    let fulltext = quote_bool(attrs.fulltext);
+    let (ty, conversion) = attrs
+        .conversion
+        .as_ref()
+        .map(|(ty, to, from)| (ty, quote!((#to, #from))))
+        .unwrap_or((&c.ty, quote!((|x| x, ::core::result::Result::Ok))));
+    if attrs.version && !is_integer(ty) {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "#[sql(version)] requires an integer column",
+        ));
+    }
+    let timestamp = match &attrs.timestamp {
+        Some(kind) => {
+            if kind == "SoftDelete" && !is_option(ty) {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "#[sql(soft_delete)] requires an Option column",
+                ));
+            }
+            quote!(::core::option::Option::Some((::sql::Timestamp::#kind, <#ty as ::sql::Now>::now)))
+        }
+        None => quote!(::core::option::Option::None),
+    };
+    Ok((
+        quote!(::sql::Column<#table, #ty, #column_type>),
+        quote! {
+            ::sql::Column::new(
+                #column_name,
+                #foreign_key,
+                #unique,
+                #primary_key,
+                #conversion,
+                #timestamp,
+                #version,
+                #fulltext
+            )
+        },
+    ))
+}
+
+/// Whether a field is cloned to be converted to its `DbValue`
+fn needs_clone(attrs: &SqlArgs) -> bool {
+    attrs.conversion.is_some() && !attrs.as_str
+}
+
+/// The `DbValue` of the field at `place`, which is cloned only to be converted with `db_as`
+fn field_db_value(c: &Field, attrs: &SqlArgs, place: TokenStream2) -> TokenStream2 {
+    match &attrs.conversion {
+        Some(_) if attrs.as_str => {
+            quote!(::sql::DbColumnType::to_db(&::std::string::ToString::to_string(&#place)))
+        }
+        Some((ty, to, _)) => {
+            let field_ty = &c.ty;
+            quote!({
+                let to_db: fn(#field_ty) -> #ty = #to;
+                ::sql::DbColumnType::to_db(&to_db(::core::clone::Clone::clone(&#place)))
+            })
+        }
+        None => quote!(::sql::DbColumnType::to_db(&#place)),
+    }
+}
+
+fn flatten_prefix(table: &Ident, field_name: &Ident) -> Ident {
+    format_ident!("__sql_prefix_{}_{}", table, field_name)
+}
+
+/// Tuples implement `ColumnTuple` up to this arity, so wider rows are split into nested tuples
+const MAX_TUPLE: usize = 26;
+
+fn nested_tuple(items: impl IntoIterator<Item = TokenStream2>) -> TokenStream2 {
+    let items = items.into_iter().collect::<Vec<_>>();
+    if items.len() <= MAX_TUPLE {
+        quote!((#(#items,)*))
+    } else {
+        let chunks = items
+            .chunks(MAX_TUPLE)
+            .map(|chunk| nested_tuple(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        nested_tuple(chunks)
+    }
+}
+
+fn field_idents(fields: &Fields) -> Vec<Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c.ident.clone().unwrap_or_else(|| format_ident!("_{}", i)))
+        .collect()
+}
+
+fn member(i: usize, field: &Field) -> Member {
+    field
+        .ident
+        .clone()
+        .map(Member::Named)
+        .unwrap_or_else(|| Member::Unnamed(i.into()))
+}
+
+fn db_types(fields: &Fields) -> Result<Vec<Type>> {
+    fields
+        .iter()
+        .map(|c| {
+            Ok(sql_attrs(&c.attrs)?
+                .conversion
+                .as_ref()
+                .map(|t| &t.0)
+                .unwrap_or(&c.ty)
+                .clone())
+        })
+        .collect()
+}
+
+fn bounded_generics(generics: &Generics, db_type: &[Type]) -> Result<Generics> {
+    if let Some(lifetime) = generics.lifetimes().next() {
+        return Err(syn::Error::new_spanned(
+            lifetime,
+            "Tables can't be generic over lifetimes",
+        ));
+    }
+    let mut generics = generics.clone();
+    if generics.type_params().next().is_some() {
+        let params = generics
+            .type_params()
+            .map(|p| p.ident.clone())
+            .collect::<Vec<_>>();
+        let where_clause = generics.make_where_clause();
+        for param in params {
+            where_clause.predicates.push(parse_quote!(#param: 'static));
+        }
+        for ty in db_type {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::sql::DbColumnType));
+        }
+    }
+    Ok(generics)
+}
+
+fn is_option(ty: &Type) -> bool {
+    matches!(ty, Type::Path(p) if p.qself.is_none()
+        && p.path.segments.last().is_some_and(|s| s.ident == "Option"))
+}
+
+/// The integer types stored as `INT8`, which `#[sql(version)]` columns are incremented as
+fn is_integer(ty: &Type) -> bool {
+    const INTEGERS: [&str; 7] = ["u8", "i8", "u16", "i16", "u32", "i32", "i64"];
+    matches!(ty, Type::Path(p) if p.qself.is_none()
+        && p.path.get_ident().is_some_and(|i| INTEGERS.contains(&&*i.to_string())))
+}
+
 fn quote_bool(x: bool) -> TokenStream2 {
     if x {
         quote!(true)
@@ -0,0 +1,414 @@
+use std::{collections::HashMap, ops::ControlFlow};
+
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use sqlparser::{
+    ast::{
+        visit_expressions, visit_expressions_mut, visit_relations_mut, BinaryOperator, Expr as SqlExpr,
+        Ident as SqlIdent, ObjectName, Query, SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, Value,
+    },
+    dialect::GenericDialect,
+    parser::Parser,
+};
+use syn::{
+    parse::{Parse, ParseStream},
+    Expr, Ident, LitStr, Result, Token,
+};
+
+const MARKER: &str = "__sql_";
+
+pub(crate) struct SqlInput {
+    query: LitStr,
+    params: Vec<Expr>,
+}
+
+impl Parse for SqlInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let query = input.parse::<LitStr>()?;
+        let mut params = vec![];
+        while input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            params.push(input.parse::<Expr>()?);
+        }
+        Ok(Self { query, params })
+    }
+}
+
+/// Tables referenced by the query, by the name or alias they are referred to with
+struct Scope {
+    span: Span,
+    bindings: HashMap<String, (Ident, bool)>,
+    tables: Vec<Ident>,
+}
+
+impl Scope {
+    fn table(&self, name: &ObjectName) -> Result<Ident> {
+        match name.0.as_slice() {
+            [ident] => syn::parse_str::<Ident>(&ident.value)
+                .map(|table| Ident::new(&table.to_string(), self.span))
+                .map_err(|_| self.error(format!("`{}` is not a Rust type name", ident.value))),
+            _ => Err(self.error(format!("Qualified table name `{}` is not supported", name))),
+        }
+    }
+
+    fn add(&mut self, factor: &TableFactor) -> Result<()> {
+        match factor {
+            TableFactor::Table {
+                name,
+                alias,
+                args: None,
+                ..
+            } => {
+                let table = self.table(name)?;
+                let (binding, is_alias) = match alias {
+                    Some(alias) => (alias.name.value.clone(), true),
+                    None => (name.0[0].value.clone(), false),
+                };
+                if !self.tables.contains(&table) {
+                    self.tables.push(table.clone());
+                }
+                match self.bindings.insert(binding.clone(), (table.clone(), is_alias)) {
+                    Some(previous) if previous != (table, is_alias) => Err(self.error(format!(
+                        "Table name or alias `{}` refers to different tables",
+                        binding
+                    ))),
+                    _ => Ok(()),
+                }
+            }
+            TableFactor::Derived { subquery, .. } => self.add_query(subquery),
+            TableFactor::NestedJoin {
+                table_with_joins, ..
+            } => self.add_from(table_with_joins),
+            _ => Err(self.error(format!("Unsupported table expression `{}`", factor))),
+        }
+    }
+
+    fn add_from(&mut self, from: &TableWithJoins) -> Result<()> {
+        self.add(&from.relation)?;
+        from.joins.iter().try_for_each(|join| self.add(&join.relation))
+    }
+
+    fn add_query(&mut self, query: &Query) -> Result<()> {
+        if query.with.is_some() {
+            return Err(self.error("WITH clauses are not supported"));
+        }
+        match &*query.body {
+            SetExpr::Select(select) => select.from.iter().try_for_each(|from| self.add_from(from)),
+            _ => Err(self.error("Only plain SELECT queries are supported")),
+        }
+    }
+
+    /// Resolves a column reference, optionally qualified by a table name or alias, to its table
+    fn resolve(&self, qualifier: Option<&SqlIdent>, column: &SqlIdent) -> Result<(Ident, Ident)> {
+        let table = match qualifier {
+            Some(qualifier) => {
+                self.bindings
+                    .get(&qualifier.value)
+                    .ok_or_else(|| self.error(format!("Unknown table or alias `{}`", qualifier.value)))?
+                    .0
+                    .clone()
+            }
+            None => match self.tables.as_slice() {
+                [table] => table.clone(),
+                _ => {
+                    return Err(self.error(format!(
+                        "Column `{}` must be qualified with a table name or alias",
+                        column.value
+                    )))
+                }
+            },
+        };
+        let column = syn::parse_str::<Ident>(&column.value)
+            .map(|column| Ident::new(&column.to_string(), self.span))
+            .map_err(|_| self.error(format!("`{}` is not a Rust field name", column.value)))?;
+        Ok((table, column))
+    }
+
+    /// The column `expr` refers to, if it's a column reference
+    fn column(&self, expr: &SqlExpr) -> Option<(Ident, Ident)> {
+        match expr {
+            SqlExpr::Identifier(column) => self.resolve(None, column).ok(),
+            SqlExpr::CompoundIdentifier(idents) if idents.len() == 2 => {
+                self.resolve(Some(&idents[0]), &idents[1]).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> syn::Error {
+        syn::Error::new(self.span, message)
+    }
+}
+
+/// The column each `?` is compared with, in the order the placeholders are visited
+fn placeholder_columns(scope: &Scope, query: &Query) -> Vec<Option<(Ident, Ident)>> {
+    fn placeholder(expr: &SqlExpr) -> Option<*const SqlExpr> {
+        matches!(expr, SqlExpr::Value(Value::Placeholder(_))).then_some(expr as *const _)
+    }
+
+    // Expressions are visited before their operands, so each placeholder is bound before it's reached
+    let mut bound = HashMap::new();
+    let mut columns = vec![];
+    let _ = visit_expressions(query, |expr| {
+        let mut bind = |column: &SqlExpr, operands: &mut dyn Iterator<Item = &SqlExpr>| {
+            if let Some(column) = scope.column(column) {
+                for operand in operands.filter_map(placeholder) {
+                    bound.insert(operand, column.clone());
+                }
+            }
+        };
+        match expr {
+            SqlExpr::BinaryOp {
+                left,
+                op:
+                    BinaryOperator::Eq
+                    | BinaryOperator::NotEq
+                    | BinaryOperator::Lt
+                    | BinaryOperator::LtEq
+                    | BinaryOperator::Gt
+                    | BinaryOperator::GtEq,
+                right,
+            } => {
+                bind(left, &mut std::iter::once(&**right));
+                bind(right, &mut std::iter::once(&**left));
+            }
+            SqlExpr::InList { expr, list, .. } => bind(expr, &mut list.iter()),
+            SqlExpr::Between { expr, low, high, .. } => bind(expr, &mut [&**low, &**high].into_iter()),
+            SqlExpr::Like { expr, pattern, .. } | SqlExpr::ILike { expr, pattern, .. } => {
+                bind(expr, &mut std::iter::once(&**pattern))
+            }
+            SqlExpr::Value(Value::Placeholder(_)) => {
+                columns.push(bound.get(&(expr as *const _)).cloned());
+            }
+            _ => {}
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    columns
+}
+
+/// Replaces parts of the statement with markers that are substituted by Rust expressions at runtime, which may quote
+/// table names with `table`
+struct Pieces(Vec<TokenStream2>);
+
+impl Pieces {
+    fn marker(&mut self, piece: TokenStream2) -> SqlIdent {
+        self.0.push(piece);
+        SqlIdent::new(format!("{}{}__", MARKER, self.0.len() - 1))
+    }
+
+    fn table(&mut self, table: &Ident) -> SqlIdent {
+        self.marker(quote!(table(<#table as ::sql::Table>::TABLE_NAME)))
+    }
+
+    fn column(&mut self, table: &Ident, column: &Ident) -> SqlIdent {
+        self.marker(quote!(::std::format!("\"{}\"", ::sql::DynColumn::name(&<#table>::#column))))
+    }
+
+    fn render(self, query: &str) -> TokenStream2 {
+        let mut parts = vec![];
+        let mut rest = query;
+        while let Some(start) = rest.find(MARKER) {
+            let after = &rest[start + MARKER.len()..];
+            let end = after.find("__").expect("unterminated marker");
+            let n = after[..end].parse::<usize>().expect("invalid marker");
+            let literal = &rest[..start];
+            parts.push(quote!(#literal));
+            let piece = &self.0[n];
+            parts.push(quote!(&#piece));
+            rest = &after[end + 2..];
+        }
+        parts.push(quote!(#rest));
+        quote! {
+            |table: &dyn ::core::ops::Fn(&str) -> ::std::string::String| {
+                let mut query = ::std::string::String::new();
+                #(query.push_str(#parts);)*
+                query
+            }
+        }
+    }
+}
+
+pub(crate) fn sql_impl(input: SqlInput) -> Result<TokenStream2> {
+    let span = input.query.span();
+    let sql = input.query.value();
+    if sql.contains(MARKER) {
+        return Err(syn::Error::new(span, format!("Queries can't contain `{}`", MARKER)));
+    }
+    let mut statements =
+        Parser::parse_sql(&GenericDialect {}, &sql).map_err(|e| syn::Error::new(span, e))?;
+    let mut query = match statements.pop() {
+        Some(Statement::Query(query)) if statements.is_empty() => query,
+        _ => return Err(syn::Error::new(span, "Expected a single SELECT query")),
+    };
+
+    let mut scope = Scope {
+        span,
+        bindings: HashMap::new(),
+        tables: vec![],
+    };
+    scope.add_query(&query)?;
+    let nested = visit_expressions(&query, |expr| {
+        match expr {
+            SqlExpr::Subquery(subquery)
+            | SqlExpr::InSubquery { subquery, .. }
+            | SqlExpr::Exists { subquery, .. } => scope.add_query(subquery),
+            _ => Ok(()),
+        }
+        .map_or_else(ControlFlow::Break, ControlFlow::Continue)
+    });
+    if let ControlFlow::Break(e) = nested {
+        return Err(e);
+    }
+
+    let param_columns = placeholder_columns(&scope, &query);
+
+    let select = match &mut *query.body {
+        SetExpr::Select(select) => select,
+        _ => unreachable!(),
+    };
+    let aliases = select
+        .projection
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // The result columns must all belong to a single table to form a `ColumnTuple`
+    let mut pieces = Pieces(vec![]);
+    let (result_table, columns) = match select.projection.as_slice() {
+        [SelectItem::Wildcard(_)] => match scope.tables.as_slice() {
+            [table] => {
+                let table = table.clone();
+                let columns = quote!(<#table as ::sql::Table>::COLUMNS);
+                select.projection = vec![SelectItem::UnnamedExpr(SqlExpr::Identifier(pieces.marker(
+                    quote!(::sql::raw::column_list(&#columns, ::core::option::Option::None)),
+                )))];
+                (table, columns)
+            }
+            _ => return Err(scope.error("`*` must be qualified with a table name or alias")),
+        },
+        [SelectItem::QualifiedWildcard(name, _)] => {
+            let qualifier = match name.0.as_slice() {
+                [qualifier] => qualifier,
+                _ => return Err(scope.error(format!("Unknown table or alias `{}`", name))),
+            };
+            let (table, is_alias) = scope
+                .bindings
+                .get(&qualifier.value)
+                .cloned()
+                .ok_or_else(|| scope.error(format!("Unknown table or alias `{}`", name)))?;
+            let columns = quote!(<#table as ::sql::Table>::COLUMNS);
+            let qualifier = if is_alias {
+                let alias = qualifier.to_string();
+                quote!(#alias)
+            } else {
+                quote!(&table(<#table as ::sql::Table>::TABLE_NAME))
+            };
+            select.projection = vec![SelectItem::UnnamedExpr(SqlExpr::Identifier(pieces.marker(
+                quote!(::sql::raw::column_list(&#columns, ::core::option::Option::Some(#qualifier))),
+            )))];
+            (table, columns)
+        }
+        projection => {
+            let columns = projection
+                .iter()
+                .map(|item| {
+                    let expr = match item {
+                        SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => expr,
+                        _ => return Err(scope.error("`*` must be the only selected item")),
+                    };
+                    match expr {
+                        SqlExpr::Identifier(column) => scope.resolve(None, column),
+                        SqlExpr::CompoundIdentifier(idents) if idents.len() == 2 => {
+                            scope.resolve(Some(&idents[0]), &idents[1])
+                        }
+                        _ => Err(scope.error(format!("Only columns can be selected, found `{}`", expr))),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let table = columns[0].0.clone();
+            if columns.iter().any(|(t, _)| *t != table) {
+                return Err(scope.error("All selected columns must belong to the same table"));
+            }
+            let columns = crate::nested_tuple(columns.iter().map(|(_, c)| quote!(<#table>::#c)));
+            (table.clone(), columns)
+        }
+    };
+
+    let mut placeholders = 0;
+    let rewritten = visit_expressions_mut(&mut query, |expr| {
+        let result = match expr {
+            SqlExpr::Identifier(column) if column.value.starts_with(MARKER) => Ok(()),
+            SqlExpr::Identifier(column) if aliases.contains(&column.value) => Ok(()),
+            SqlExpr::Identifier(column) => scope
+                .resolve(None, column)
+                .map(|(table, column)| *expr = SqlExpr::Identifier(pieces.column(&table, &column))),
+            SqlExpr::CompoundIdentifier(idents) if idents.len() == 2 => {
+                scope.resolve(Some(&idents[0]), &idents[1]).map(|(table, column)| {
+                    if !scope.bindings[&idents[0].value].1 {
+                        idents[0] = pieces.table(&table);
+                    }
+                    idents[1] = pieces.column(&table, &column);
+                })
+            }
+            SqlExpr::CompoundIdentifier(_) => {
+                Err(scope.error(format!("Unsupported column reference `{}`", expr)))
+            }
+            SqlExpr::Value(Value::Placeholder(p)) if p == "?" => {
+                placeholders += 1;
+                Ok(())
+            }
+            SqlExpr::Value(Value::Placeholder(p)) => Err(scope.error(format!(
+                "Unsupported placeholder `{}`, use `?` instead",
+                p
+            ))),
+            _ => Ok(()),
+        };
+        result.map_or_else(ControlFlow::Break, ControlFlow::Continue)
+    });
+    if let ControlFlow::Break(e) = rewritten {
+        return Err(e);
+    }
+    let rewritten = visit_relations_mut(&mut query, |name| {
+        match scope.table(name) {
+            Ok(table) => {
+                *name = ObjectName(vec![pieces.table(&table)]);
+                ControlFlow::Continue(())
+            }
+            Err(e) => ControlFlow::Break(e),
+        }
+    });
+    if let ControlFlow::Break(e) = rewritten {
+        return Err(e);
+    }
+
+    if placeholders != input.params.len() {
+        return Err(syn::Error::new(
+            span,
+            format!(
+                "Query has {} placeholders, but {} parameters were given",
+                placeholders,
+                input.params.len()
+            ),
+        ));
+    }
+    let query = pieces.render(&query.to_string());
+    // Parameters compared with a column must have its Rust type
+    let params = input.params.iter().zip(param_columns).map(|(param, column)| match column {
+        Some((table, column)) => quote!(<#table>::#column.to_db(#param)),
+        None => quote!(::sql::DbColumnType::to_db(&(#param))),
+    });
+    Ok(quote! {
+        ::sql::raw::TypedQuery::<#result_table, _>::new(
+            #query,
+            ::std::vec![#(#params,)*],
+            #columns,
+        )
+    })
+}
@@ -1,12 +1,13 @@
 use std::net::{IpAddr, Ipv4Addr};
 
 use futures::Future;
-use sql::{Database, Ordering, Pool, Table};
+use sql::{Database, FromRow, Ordering, Pool, Table};
 
 #[allow(dead_code)]
 #[derive(Debug, Table)]
 #[sql(name = "12345")]
 struct Test {
+    #[sql(primary_key)]
     id: i32,
     name: String,
     #[sql(name = "hello world", unique)]
@@ -19,6 +20,14 @@ struct Test {
     z: IpAddr,
 }
 
+#[allow(dead_code)]
+#[derive(Debug, FromRow)]
+#[sql(view_of = Test)]
+struct TestName {
+    id: i32,
+    name: String,
+}
+
 async fn print(pool: &Pool<impl Database>) -> anyhow::Result<()> {
     let test = pool
         .select(Test::COLUMNS)
@@ -50,6 +59,17 @@ async fn main() -> anyhow::Result<()> {
             .execute()
             .await?;
         print(&pool).await?;
+        pool.insert(&Test {
+            id: 24,
+            name: "5678".to_string(),
+            name_2: Some("5678".to_string()),
+            name_3: None,
+            r: 5678.9,
+            z: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        })
+        .execute()
+        .await?;
+        print(&pool).await?;
         pool.update()
             .set(Test::name_2, Some("1234".to_owned()))
             .set(Test::r, 12345.6)
@@ -57,6 +77,19 @@ async fn main() -> anyhow::Result<()> {
             .execute()
             .await?;
         print(&pool).await?;
+        let mut rows = pool.select(Test::COLUMNS).fetch_all::<Test>().await?;
+        for row in &mut rows {
+            row.name_3 = Some(row.id.into());
+        }
+        for row in &rows {
+            pool.update_row(row).execute().await?;
+        }
+        print(&pool).await?;
+        let names = pool
+            .select(TestName::COLUMNS)
+            .fetch_all::<TestName>()
+            .await?;
+        println!("{:?}", names);
         pool.delete_where(Test::id.equals(23)).execute().await?;
         print(&pool).await?;
         Ok(())
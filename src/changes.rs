@@ -0,0 +1,86 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use futures::{stream, Stream};
+use rusqlite::Action;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{Pool, Result, Sqlite, Table};
+
+pub(crate) type Subscribers = Arc<Mutex<Vec<(&'static str, UnboundedSender<(ChangeOp, i64)>)>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A row of `T` changed through the pool, identified by its `rowid`
+pub struct Change<T> {
+    pub op: ChangeOp,
+    pub rowid: i64,
+    table: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Change<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Change<T> {}
+
+impl<T: Table> fmt::Debug for Change<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Change")
+            .field("table", &T::TABLE_NAME)
+            .field("op", &self.op)
+            .field("rowid", &self.rowid)
+            .finish()
+    }
+}
+
+impl Pool<Sqlite> {
+    /// Rows of `T` inserted, updated or deleted through this pool from now on
+    ///
+    /// Changes are reported by SQLite's update hook as soon as a statement makes them, so they include changes that
+    /// a transaction later rolls back. Changes made by other processes, rows replaced by `ON CONFLICT REPLACE` and
+    /// `WITHOUT ROWID` tables aren't reported.
+    pub fn subscribe<T: Table>(&self) -> Result<impl Stream<Item = Change<T>>> {
+        let subscribers = self
+            .0
+            .subscribers()
+            .ok_or_else(|| anyhow!("Changes can only be subscribed to on pools created by Pool::open"))?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        subscribers.lock().unwrap().push((T::TABLE_NAME, tx));
+        Ok(stream::poll_fn(move |cx| {
+            rx.poll_recv(cx).map(|change| {
+                change.map(|(op, rowid)| Change {
+                    op,
+                    rowid,
+                    table: PhantomData,
+                })
+            })
+        }))
+    }
+}
+
+/// Sends a change reported by a connection's update hook to the subscribers of its table, dropping those that are
+/// gone
+pub(crate) fn notify(subscribers: &Subscribers, action: Action, table: &str, rowid: i64) {
+    let op = match action {
+        Action::SQLITE_INSERT => ChangeOp::Insert,
+        Action::SQLITE_UPDATE => ChangeOp::Update,
+        Action::SQLITE_DELETE => ChangeOp::Delete,
+        _ => return,
+    };
+    subscribers
+        .lock()
+        .unwrap()
+        .retain(|(name, tx)| *name != table || tx.send((op, rowid)).is_ok());
+}
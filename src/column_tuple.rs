@@ -1,57 +1,68 @@
 use crate::{db_value::DbType, Column, DbColumnType, DbValue, DynColumn, Result, Table};
 
-macro_rules! count {
-    () => { 0 };
-    ($t:tt $($rest:tt)*) => {
-        1 + count!($($rest)*)
-    }
-}
-
 pub trait Sealed {}
 pub trait ColumnTuple<T: Table>: Sealed + Copy + Send + Sync + 'static {
     type Out;
 
     const N: usize;
 
-    fn try_from_values(&self, values: impl FnMut(DbType) -> DbValue) -> Result<Self::Out>;
+    fn try_from_values(&self, values: impl FnMut(DbType) -> Result<DbValue>) -> Result<Self::Out>;
 
     fn to_values(&self, values: Self::Out) -> Vec<DbValue>;
 
     fn apply_columns<'a>(&'a self, f: impl FnMut(&'a dyn DynColumn<T>));
 }
 
+impl<T: Table, U: DbColumnType, V> Sealed for Column<T, U, V> {}
+impl<T: Table, U: DbColumnType, V: 'static> ColumnTuple<T> for Column<T, U, V> {
+    type Out = V;
+    const N: usize = 1;
+
+    fn try_from_values(&self, mut values: impl FnMut(DbType) -> Result<DbValue>) -> Result<Self::Out> {
+        self.from_db(values(U::db_type())?)
+    }
+
+    fn to_values(&self, values: Self::Out) -> Vec<DbValue> {
+        vec![self.to_db(values)]
+    }
+
+    fn apply_columns<'a>(&'a self, mut f: impl FnMut(&'a dyn DynColumn<T>)) {
+        f(self)
+    }
+}
+
 macro_rules! impl_tuple {
     ($($types:ident)*) => {
-        paste::paste! {
-            impl<Ta: Table, $([< DBTy $types >]: DbColumnType, [< RSTy $types >]),*> Sealed for ($(Column<Ta, [< DBTy $types >], [< RSTy $types >]>,)*) {}
-            impl<Ta: Table, $([< DBTy $types >]: DbColumnType, [< RSTy $types >]),*> ColumnTuple<Ta> for ($(Column<Ta, [< DBTy $types >], [< RSTy $types >]>,)*) {
-                type Out = ($([< RSTy $types >],)*);
-                const N: usize = count!($($types)*);
+        impl<$($types: Sealed),*> Sealed for ($($types,)*) {}
+        impl<Ta: Table, $($types: ColumnTuple<Ta>),*> ColumnTuple<Ta> for ($($types,)*) {
+            type Out = ($($types::Out,)*);
+            const N: usize = 0 $(+ $types::N)*;
 
-                fn try_from_values(&self, mut values: impl FnMut(DbType) -> DbValue) -> Result<Self::Out> {
-                    #[allow(non_snake_case)]
-                    let ($($types,)*) = self;
-                    Ok((
-                        $(
-                            $types.from_db(values([< DBTy $types >]::db_type()))?,
-                        )*
-                    ))
-                }
+            fn try_from_values(&self, mut values: impl FnMut(DbType) -> Result<DbValue>) -> Result<Self::Out> {
+                #[allow(non_snake_case)]
+                let ($($types,)*) = self;
+                Ok((
+                    $(
+                        $types.try_from_values(&mut values)?,
+                    )*
+                ))
+            }
 
-                fn to_values(&self, values: Self::Out) -> Vec<DbValue> {
-                    #![allow(non_snake_case)]
-                    let ($([< Col $types >],)*) = self;
+            fn to_values(&self, values: Self::Out) -> Vec<DbValue> {
+                #![allow(non_snake_case)]
+                let ($($types,)*) = self;
+                let mut db_values = Vec::with_capacity(Self::N);
+                paste::paste! {
                     let ($([< Val $types >],)*) = values;
-                    vec![
-                        $([< Col $types >].to_db([< Val $types >])),*
-                    ]
+                    $(db_values.extend($types.to_values([< Val $types >]));)*
                 }
+                db_values
+            }
 
-                fn apply_columns<'a>(&'a self, mut f: impl FnMut(&'a dyn DynColumn<Ta>)) {
-                    #[allow(non_snake_case)]
-                    let ($($types,)*) = self;
-                    $(f($types);)*
-                }
+            fn apply_columns<'a>(&'a self, mut f: impl FnMut(&'a dyn DynColumn<Ta>)) {
+                #[allow(non_snake_case)]
+                let ($($types,)*) = self;
+                $($types.apply_columns(&mut f);)*
             }
         }
     }
@@ -1,16 +1,38 @@
-use crate::{Column, DbColumnType, DbValue, DynColumn, Table};
+use anyhow::bail;
+
+use crate::{
+    expr::{build_operands, ExprE},
+    raw::{count_params, translate_params},
+    select::{QueryBuilder, Select}, Column, ColumnTuple, Database, DbColumnType, DbValue, DynColumn,
+    Dialect, QueryContext, Result, Table,
+};
 
 pub struct CondExpr<T: Table>(pub(crate) CondExprE<T>);
 pub(crate) enum CondExprE<T: Table> {
     ColumnEquals(Box<dyn DynColumn<T> + Send>, DbValue),
+    Compare(ExprE<T>, &'static str, ExprE<T>),
     ColumnIsNotNull(Box<dyn DynColumn<T> + Send>),
     ColumnIsNull(Box<dyn DynColumn<T> + Send>),
+    ColumnIn(Box<dyn DynColumn<T> + Send>, Vec<DbValue>),
+    Array(Box<dyn DynColumn<T> + Send>, ArrayOp, DbValue),
+    Matches(Box<dyn DynColumn<T> + Send>, String),
+    #[cfg(feature = "serde")]
+    JsonContains(Box<dyn DynColumn<T> + Send>, serde_json::Value),
+    ColumnInSubquery(Box<dyn DynColumn<T> + Send>, Box<dyn Subquery>),
+    Exists(Box<dyn Subquery>),
+    Raw(String, Vec<DbValue>),
     All(Vec<CondExprE<T>>),
     Any(Vec<CondExprE<T>>),
     True,
     False,
 }
 
+pub(crate) enum ArrayOp {
+    Contains,
+    Overlaps,
+    Any,
+}
+
 impl<T: Table> CondExpr<T> {
     #[must_use]
     pub fn and(self, other: CondExpr<T>) -> Self {
@@ -27,15 +49,118 @@ impl<T: Table> CondExpr<T> {
         Self(CondExprE::Any(any.into_iter().map(|x| x.0).collect()))
     }
 
+    /// `subquery` returns any row, which holds for all rows alike as it can't refer to them; see `exists_where`
+    pub fn exists<Db: Database, U: Table, Columns: ColumnTuple<U>>(
+        subquery: QueryBuilder<'_, Db, U, Columns>,
+    ) -> Self {
+        Self(CondExprE::Exists(Box::new(subquery.into_select())))
+    }
+
+    /// `subquery` returns any row whose `column` equals the `outer` column of this row, as in
+    /// `EXISTS (SELECT .. FROM "Post" WHERE .. AND "Post"."user_id" = "User"."id")`
+    ///
+    /// The subquery must be on another table.
+    pub fn exists_where<Db: Database, U: Table, Columns: ColumnTuple<U>, X: DbColumnType, V, V2>(
+        subquery: QueryBuilder<'_, Db, U, Columns>,
+        column: Column<U, X, V>,
+        outer: Column<T, X, V2>,
+    ) -> Self {
+        let mut select = subquery.into_select();
+        select.correlation = Some(Correlation {
+            column: column.name,
+            outer_table: T::TABLE_NAME,
+            outer_column: outer.name,
+        });
+        Self(CondExprE::Exists(Box::new(select)))
+    }
+
+    /// A condition written in SQL, with a `?` in place of each of `params` and `??` for a literal `?`
+    ///
+    /// A wrong number of `params` is reported when the query runs.
+    pub fn raw(condition: impl Into<String>, params: impl IntoIterator<Item = DbValue>) -> Self {
+        Self(CondExprE::Raw(condition.into(), params.into_iter().collect()))
+    }
+
     pub const TRUE: Self = Self(CondExprE::True);
     pub const FALSE: Self = Self(CondExprE::False);
 }
 
+/// The column of a correlated subquery's table that must equal a column of the outer query's table
+pub(crate) struct Correlation {
+    pub(crate) column: &'static str,
+    pub(crate) outer_table: &'static str,
+    pub(crate) outer_column: &'static str,
+}
+
+pub(crate) trait Subquery: Send {
+    fn check(&self, dialect: Dialect) -> Result<()>;
+
+    fn build(
+        self: Box<Self>,
+        params: &mut Vec<DbValue>,
+        ctx: &mut dyn QueryContext,
+    ) -> String;
+}
+
+impl<T: Table, Columns: ColumnTuple<T>> Subquery for Select<T, Columns> {
+    fn check(&self, dialect: Dialect) -> Result<()> {
+        Select::check(self, dialect)
+    }
+
+    fn build(
+        self: Box<Self>,
+        params: &mut Vec<DbValue>,
+        ctx: &mut dyn QueryContext,
+    ) -> String {
+        Select::build(*self, false, params, ctx)
+    }
+}
+
 impl<T: Table, U: DbColumnType, V> Column<T, U, V> {
     pub fn equals(self, v: V) -> CondExpr<T> {
         let u = self.to_db(v);
         CondExpr(CondExprE::ColumnEquals(Box::new(self), u))
     }
+
+    /// Like `equals`, with a value already converted by this column
+    #[doc(hidden)]
+    pub fn equals_db(self, value: DbValue) -> CondExpr<T> {
+        CondExpr(CondExprE::ColumnEquals(Box::new(self), value))
+    }
+
+    pub fn is_in(self, values: impl IntoIterator<Item = V>) -> CondExpr<T> {
+        let values = values.into_iter().map(|v| self.to_db(v)).collect();
+        CondExpr(CondExprE::ColumnIn(Box::new(self), values))
+    }
+
+    pub fn in_subquery<Db: Database, T2: Table, V2>(
+        self,
+        subquery: QueryBuilder<'_, Db, T2, (Column<T2, U, V2>,)>,
+    ) -> CondExpr<T> {
+        CondExpr(CondExprE::ColumnInSubquery(
+            Box::new(self),
+            Box::new(subquery.into_select()),
+        ))
+    }
+}
+
+impl<T: Table, U: DbColumnType, V> Column<T, Vec<U>, V> {
+    /// The array contains all of `values`
+    pub fn contains(self, values: impl IntoIterator<Item = U>) -> CondExpr<T> {
+        let values = values.into_iter().collect::<Vec<_>>().to_db();
+        CondExpr(CondExprE::Array(Box::new(self), ArrayOp::Contains, values))
+    }
+
+    /// The array contains any of `values`
+    pub fn overlaps(self, values: impl IntoIterator<Item = U>) -> CondExpr<T> {
+        let values = values.into_iter().collect::<Vec<_>>().to_db();
+        CondExpr(CondExprE::Array(Box::new(self), ArrayOp::Overlaps, values))
+    }
+
+    /// The array contains `value`
+    pub fn any(self, value: U) -> CondExpr<T> {
+        CondExpr(CondExprE::Array(Box::new(self), ArrayOp::Any, value.to_db()))
+    }
 }
 
 impl<T: Table, U: DbColumnType, V> Column<T, Option<U>, V> {
@@ -48,29 +173,114 @@ impl<T: Table, U: DbColumnType, V> Column<T, Option<U>, V> {
     }
 }
 
+/// Checks what the type system can't for `cond`, such as subqueries built for another database
+pub(crate) fn check_condition<T: Table>(cond: &CondExprE<T>, dialect: Dialect) -> Result<()> {
+    match cond {
+        CondExprE::ColumnInSubquery(_, subquery) | CondExprE::Exists(subquery) => subquery.check(dialect),
+        CondExprE::All(conds) | CondExprE::Any(conds) => {
+            conds.iter().try_for_each(|cond| check_condition(cond, dialect))
+        }
+        CondExprE::Raw(condition, params) if count_params(condition) != params.len() => bail!(
+            "Raw condition {:?} has {} placeholders, but {} parameters were given",
+            condition,
+            count_params(condition),
+            params.len()
+        ),
+        CondExprE::Matches(col, _) => crate::fulltext::check_fulltext(&**col),
+        _ => Ok(()),
+    }
+}
 
-pub(crate) fn build_condition_query(
-    cond: CondExprE<impl Table>,
+pub(crate) fn build_condition_query<T: Table>(
+    cond: CondExprE<T>,
     params: &mut Vec<DbValue>,
-    next_param: &mut impl FnMut() -> String,
+    ctx: &mut dyn QueryContext,
 ) -> String {
     match cond {
         CondExprE::ColumnEquals(col, val) => {
             params.push(val);
-            format!("\"{}\" = {}", col.name(), next_param())
+            format!("\"{}\" = {}", col.name(), ctx.typed_param(&col.db_type()))
+        }
+        CondExprE::Compare(lhs, op, rhs) => {
+            let (lhs, rhs) = build_operands(lhs, rhs, None, params, ctx);
+            format!("{} {} {}", lhs, op, rhs)
         }
         CondExprE::ColumnIsNotNull(col) => format!("\"{}\" IS NOT NULL", col.name()),
         CondExprE::ColumnIsNull(col) => format!("\"{}\" IS NULL", col.name()),
-        CondExprE::All(conds) => conds
-            .into_iter()
-            .map(|cond| build_condition_query(cond, params, next_param))
-            .collect::<Vec<_>>()
-            .join(" AND "),
-        CondExprE::Any(conds) => conds
-            .into_iter()
-            .map(|cond| build_condition_query(cond, params, next_param))
-            .collect::<Vec<_>>()
-            .join(" OR "),
+        CondExprE::ColumnIn(_, values) if values.is_empty() => "FALSE".to_owned(),
+        CondExprE::ColumnIn(col, values) => format!(
+            "\"{}\" IN ({})",
+            col.name(),
+            values
+                .into_iter()
+                .map(|val| {
+                    params.push(val);
+                    ctx.typed_param(&col.db_type())
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        CondExprE::Array(col, op, value) => {
+            params.push(value);
+            let param = ctx.next_param();
+            match (ctx.dialect(), op) {
+                (Dialect::Postgres, ArrayOp::Contains) => format!("\"{}\" @> {}", col.name(), param),
+                (Dialect::Postgres, ArrayOp::Overlaps) => format!("\"{}\" && {}", col.name(), param),
+                (Dialect::Postgres, ArrayOp::Any) => format!("{} = ANY(\"{}\")", param, col.name()),
+                // Arrays are JSON text on SQLite; the column is qualified as `json_each` has a `value` column too
+                (Dialect::Sqlite, ArrayOp::Contains) => format!(
+                    "NOT EXISTS (SELECT 1 FROM json_each({}) AS a WHERE a.value NOT IN \
+                     (SELECT b.value FROM json_each({}.\"{}\") AS b))",
+                    param,
+                    ctx.table(T::TABLE_NAME),
+                    col.name()
+                ),
+                (Dialect::Sqlite, ArrayOp::Overlaps) => format!(
+                    "EXISTS (SELECT 1 FROM json_each({}) AS a WHERE a.value IN \
+                     (SELECT b.value FROM json_each({}.\"{}\") AS b))",
+                    param,
+                    ctx.table(T::TABLE_NAME),
+                    col.name()
+                ),
+                (Dialect::Sqlite, ArrayOp::Any) => format!(
+                    "EXISTS (SELECT 1 FROM json_each({}.\"{}\") AS a WHERE a.value = {})",
+                    ctx.table(T::TABLE_NAME),
+                    col.name(),
+                    param
+                ),
+            }
+        }
+        CondExprE::Matches(col, query) => crate::fulltext::build_matches(&*col, query, params, ctx),
+        #[cfg(feature = "serde")]
+        CondExprE::JsonContains(col, value) => crate::json::build_contains::<T>(col.name(), value, params, ctx),
+        CondExprE::ColumnInSubquery(col, subquery) => format!(
+            "\"{}\" IN ({})",
+            col.name(),
+            subquery.build(params, ctx)
+        ),
+        CondExprE::Exists(subquery) => format!("EXISTS ({})", subquery.build(params, ctx)),
+        CondExprE::Raw(condition, values) => {
+            params.extend(values);
+            format!("({})", translate_params(&condition, ctx))
+        }
+        CondExprE::All(conds) if conds.is_empty() => "TRUE".to_owned(),
+        CondExprE::Any(conds) if conds.is_empty() => "FALSE".to_owned(),
+        CondExprE::All(conds) => format!(
+            "({})",
+            conds
+                .into_iter()
+                .map(|cond| build_condition_query(cond, params, ctx))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        ),
+        CondExprE::Any(conds) => format!(
+            "({})",
+            conds
+                .into_iter()
+                .map(|cond| build_condition_query(cond, params, ctx))
+                .collect::<Vec<_>>()
+                .join(" OR ")
+        ),
         CondExprE::True => "TRUE".to_owned(),
         CondExprE::False => "FALSE".to_owned(),
     }
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use crate::{ColumnTuple, Database, Pool, Result, Table};
+use crate::{fulltext, qualified_table, ColumnTuple, Database, Pool, Result, Table};
 
 pub struct CreateTableBuilder<'pool, Db: Database, T: Table> {
     pool: &'pool Pool<Db>,
@@ -29,34 +29,58 @@ impl<Db: Database, T: Table> CreateTableBuilder<'_, Db, T> {
 }
 
 impl<Db: Database, T: Table> CreateTableBuilder<'_, Db, T> {
-    fn build_query(&self) -> String {
+    fn build_query(&self, extra_columns: Vec<String>) -> String {
         let colspec = {
             let mut spec = vec![];
+            let mut primary_key = vec![];
             T::COLUMNS.apply_columns(|col| {
                 spec.push(format!(
                     "\"{}\" {} {} {}",
                     col.name(),
-                    col.db_type().name(),
-                    col.foreign_key().unwrap_or_default(),
+                    Db::type_name(&col.db_type()),
+                    col.foreign_key(self.pool.schema()).unwrap_or_default(),
                     if col.unique() { "UNIQUE" } else { "" },
-                ))
+                ));
+                if col.primary_key() {
+                    primary_key.push(format!("\"{}\"", col.name()));
+                }
             });
+            spec.extend(extra_columns);
+            if !primary_key.is_empty() {
+                spec.push(format!("PRIMARY KEY ({})", primary_key.join(", ")));
+            }
             spec.join(", ")
         };
         format!(
-            "CREATE TABLE {} \"{}\"({})",
+            "CREATE TABLE {} {}({})",
             if self.if_not_exists {
                 "IF NOT EXISTS"
             } else {
                 ""
             },
-            T::TABLE_NAME,
+            qualified_table(self.pool.schema(), T::TABLE_NAME),
             colspec
         )
     }
 
     pub async fn execute(self) -> Result<()> {
-        let query = self.build_query();
-        Db::execute(&self.pool.0, query, vec![]).await
+        let mut definitions = vec![];
+        if let Some(schema) = self.pool.schema() {
+            definitions.push(format!(
+                "CREATE SCHEMA IF NOT EXISTS \"{}\"",
+                schema.replace('"', "\"\"")
+            ));
+        }
+        T::COLUMNS.apply_columns(|col| definitions.extend(Db::type_definitions(&col.db_type())));
+        for definition in definitions {
+            Db::execute(&self.pool.0, definition, vec![]).await?;
+        }
+        let (fulltext_columns, fulltext_statements) = fulltext::definitions::<T>(Db::DIALECT, self.pool.schema())?;
+        let query = self.build_query(fulltext_columns);
+        Db::execute(&self.pool.0, query, vec![]).await?;
+        for statement in fulltext_statements {
+            Db::execute(&self.pool.0, statement, vec![]).await?;
+        }
+        Ok(())
     }
 }
@@ -1,51 +1,237 @@
+use std::ops::{Deref, DerefMut};
+
 use anyhow::bail;
 
-use crate::{DbColumnType, Result};
+use crate::{DbColumnType, Dialect, Result};
 
-use deadpool_sqlite::rusqlite::types::Value;
+/// A value as stored in the database, independent of the backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+    /// A Postgres array, stored as JSON text on SQLite
+    Array(Vec<DbValue>),
+}
 
-#[derive(Debug, Clone)]
-pub struct DbValue(pub(crate) Value);
+impl DbValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, DbValue::Null)
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            DbValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DbValue::Real(r) => Some(*r),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            DbValue::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            DbValue::Blob(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[DbValue]> {
+        match self {
+            DbValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Converts this value to a Rust value with `T`'s column conversion
+    pub fn get<T: DbColumnType>(&self) -> Result<T> {
+        T::from_db(self)
+    }
+}
 
-#[derive(Debug)]
+impl<T: DbColumnType> From<T> for DbValue {
+    fn from(value: T) -> Self {
+        value.to_db()
+    }
+}
+
+impl From<&str> for DbValue {
+    fn from(value: &str) -> Self {
+        DbValue::Text(value.to_owned())
+    }
+}
+
+impl From<&[u8]> for DbValue {
+    fn from(value: &[u8]) -> Self {
+        DbValue::Blob(value.to_owned())
+    }
+}
+
+/// How a [`DbColumnType`] is stored; implementations for custom types build it from the constructors below
+#[derive(Debug, Clone)]
 pub struct DbType(pub(crate) DbTypeE);
 
 impl DbType {
-    pub fn name(&self) -> &'static str {
+    pub fn integer() -> Self {
+        Self(DbTypeE::Integer)
+    }
+
+    pub fn real() -> Self {
+        Self(DbTypeE::Real)
+    }
+
+    pub fn text() -> Self {
+        Self(DbTypeE::Text)
+    }
+
+    pub fn blob() -> Self {
+        Self(DbTypeE::Blob)
+    }
+
+    pub fn array(element: DbType) -> Self {
+        Self(DbTypeE::Array(Box::new(element.0)))
+    }
+
+    /// This type, allowing NULL
+    pub fn nullable(self) -> Self {
+        match self.0 {
+            DbTypeE::Nullable(_) => self,
+            t => Self(DbTypeE::Nullable(Box::new(t))),
+        }
+    }
+
+    /// A column declared with the SQL type `sql_name`, whose values are exchanged with the database as `base`
+    ///
+    /// With an integer, real or text `base`, the column is read cast to it, and on Postgres values are cast to
+    /// `sql_name`, so e.g. `custom("NUMERIC(10,2)", text())` reads and writes decimals as text. SQLite converts
+    /// values to the column's affinity when storing them, so a `NUMERIC` column there reads `"12.50"` back as
+    /// `"12.5"`; declare it with a text type such as `VARCHAR(n)` to keep values as written. Raw queries and
+    /// [`sql!`](crate::sql) don't add these casts.
+    pub fn custom(sql_name: &'static str, base: DbType) -> Self {
+        match base.0 {
+            DbTypeE::Nullable(t) => Self(DbTypeE::Custom(sql_name, t)).nullable(),
+            t => Self(DbTypeE::Custom(sql_name, Box::new(t))),
+        }
+    }
+
+    pub fn name(&self) -> String {
         self.0.name()
     }
+
+    /// `column` as read from the database, cast to the base of a custom type
+    pub(crate) fn read_column(&self, column: String) -> String {
+        match self.0.custom_cast() {
+            Some((_, base)) => format!("CAST({} AS {})", column, base),
+            None => column,
+        }
+    }
+
+    /// `param` cast to a custom type on Postgres, which doesn't convert parameters to it implicitly
+    pub(crate) fn write_param(&self, param: String, dialect: Dialect) -> String {
+        match self.0.custom_cast() {
+            Some((name, base)) if dialect == Dialect::Postgres => format!("CAST(CAST({} AS {}) AS {})", param, base, name),
+            _ => param,
+        }
+    }
+
+    /// Text restricted to `variants`, stored in a native enum type called `name` where the database supports it
+    pub fn enumeration(name: &'static str, variants: &'static [&'static str]) -> Self {
+        Self(DbTypeE::Enum(name, variants))
+    }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum DbTypeE {
     Integer,
     Real,
     Text,
+    Enum(&'static str, &'static [&'static str]),
+    Blob,
+    Array(Box<DbTypeE>),
+    #[cfg(feature = "serde")]
+    Json,
+    Custom(&'static str, Box<DbTypeE>),
     Nullable(Box<DbTypeE>),
 }
 
 impl DbTypeE {
-    fn name(&self) -> &'static str {
+    /// Parses user input as a value of this type
+    pub(crate) fn parse(&self, input: &str) -> Result<DbValue> {
+        Ok(match self {
+            DbTypeE::Integer => DbValue::Integer(input.trim().parse()?),
+            DbTypeE::Real => DbValue::Real(input.trim().parse()?),
+            DbTypeE::Text => DbValue::Text(input.to_owned()),
+            DbTypeE::Enum(_, variants) if variants.contains(&input) => DbValue::Text(input.to_owned()),
+            DbTypeE::Enum(name, variants) => {
+                bail!("Invalid value {:?} for {}, expected one of {:?}", input, name, variants)
+            }
+            DbTypeE::Blob => bail!("Binary values can't be parsed from text"),
+            DbTypeE::Array(_) => bail!("Arrays can't be parsed from text"),
+            #[cfg(feature = "serde")]
+            DbTypeE::Json => bail!("JSON values can't be compared"),
+            DbTypeE::Custom(_, t) | DbTypeE::Nullable(t) => return t.parse(input),
+        })
+    }
+
+    /// The SQL name of a custom type, and the name of its base in both databases, if it can be cast to it
+    fn custom_cast(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            DbTypeE::Nullable(t) => t.custom_cast(),
+            DbTypeE::Custom(name, base) => match **base {
+                DbTypeE::Integer => Some((name, "INT8")),
+                DbTypeE::Real => Some((name, "DOUBLE PRECISION")),
+                DbTypeE::Text => Some((name, "TEXT")),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> String {
         match self {
-            DbTypeE::Integer => "INT8 NOT NULL",
-            DbTypeE::Text => "TEXT NOT NULL",
-            DbTypeE::Real => "DOUBLE PRECISION NOT NULL",
-            DbTypeE::Nullable(t) => t.name().strip_suffix(" NOT NULL").unwrap_or_else(|| t.name()),
+            DbTypeE::Integer => "INT8 NOT NULL".to_owned(),
+            DbTypeE::Text | DbTypeE::Enum(..) | DbTypeE::Array(_) => "TEXT NOT NULL".to_owned(),
+            DbTypeE::Real => "DOUBLE PRECISION NOT NULL".to_owned(),
+            DbTypeE::Blob => "BLOB NOT NULL".to_owned(),
+            #[cfg(feature = "serde")]
+            DbTypeE::Json => "TEXT NOT NULL".to_owned(),
+            DbTypeE::Custom(name, _) => format!("{} NOT NULL", name),
+            DbTypeE::Nullable(t) => strip_not_null(t.name()),
         }
     }
 }
+
+pub(crate) fn strip_not_null(name: String) -> String {
+    match name.strip_suffix(" NOT NULL") {
+        Some(name) => name.to_owned(),
+        None => name,
+    }
+}
 macro_rules! int_db_column_type {
     ($($t:ty)*) => {
         $(
             impl DbColumnType for $t {
                 fn from_db(db_value: &DbValue) -> Result<Self> {
-                    match db_value.0 {
-                        Value::Integer(i) => Ok(<$t>::try_from(i)?),
-                        _ => bail!("Expected integer, found {:?}", db_value.0),
+                    match *db_value {
+                        DbValue::Integer(i) => Ok(<$t>::try_from(i)?),
+                        _ => bail!("Expected integer, found {:?}", db_value),
                     }
                 }
             
                 fn to_db(&self) -> DbValue {
-                    DbValue(Value::Integer((*self).into()))
+                    DbValue::Integer((*self).into())
                 }
             
                 fn db_type() -> DbType {
@@ -61,14 +247,14 @@ int_db_column_type!(u8 i8 u16 i16 u32 i32 i64);
 
 impl DbColumnType for f32 {
     fn from_db(db_value: &DbValue) -> Result<Self> {
-        match db_value.0 {
-            Value::Real(i) => Ok(i as _),
-            _ => bail!("Expected float, found {:?}", db_value.0),
+        match *db_value {
+            DbValue::Real(i) => Ok(i as _),
+            _ => bail!("Expected float, found {:?}", db_value),
         }
     }
 
     fn to_db(&self) -> DbValue {
-        DbValue(Value::Real(*self as _))
+        DbValue::Real(*self as _)
     }
 
     fn db_type() -> DbType {
@@ -78,14 +264,14 @@ impl DbColumnType for f32 {
 
 impl DbColumnType for f64 {
     fn from_db(db_value: &DbValue) -> Result<Self> {
-        match db_value.0 {
-            Value::Real(i) => Ok(i as _),
-            _ => bail!("Expected float, found {:?}", db_value.0),
+        match *db_value {
+            DbValue::Real(i) => Ok(i as _),
+            _ => bail!("Expected float, found {:?}", db_value),
         }
     }
 
     fn to_db(&self) -> DbValue {
-        DbValue(Value::Real(*self as _))
+        DbValue::Real(*self as _)
     }
 
     fn db_type() -> DbType {
@@ -95,14 +281,14 @@ impl DbColumnType for f64 {
 
 impl DbColumnType for String {
     fn from_db(db_value: &DbValue) -> Result<Self> {
-        match &db_value.0 {
-            Value::Text(t) => Ok(t.clone()),
-            _ => bail!("Expected string, found {:?}", db_value.0),
+        match db_value {
+            DbValue::Text(t) => Ok(t.clone()),
+            _ => bail!("Expected string, found {:?}", db_value),
         }
     }
 
     fn to_db(&self) -> DbValue {
-        DbValue(Value::Text(self.clone()))
+        DbValue::Text(self.clone())
     }
 
     fn db_type() -> DbType {
@@ -112,8 +298,8 @@ impl DbColumnType for String {
 
 impl<T: DbColumnType> DbColumnType for Option<T> {
     fn from_db(db_value: &DbValue) -> Result<Self> {
-        match &db_value.0 {
-            Value::Null => Ok(None),
+        match db_value {
+            DbValue::Null => Ok(None),
             _ => T::from_db(db_value).map(Some),
         }
     }
@@ -121,10 +307,69 @@ impl<T: DbColumnType> DbColumnType for Option<T> {
     fn to_db(&self) -> DbValue {
         self.as_ref()
             .map(DbColumnType::to_db)
-            .unwrap_or(DbValue(Value::Null))
+            .unwrap_or(DbValue::Null)
     }
 
     fn db_type() -> DbType {
         DbType(DbTypeE::Nullable(Box::new(T::db_type().0)))
     }
 }
+
+/// An array of `T`; use [`Bytes`] rather than `Vec<u8>` for binary data
+impl<T: DbColumnType> DbColumnType for Vec<T> {
+    fn from_db(db_value: &DbValue) -> Result<Self> {
+        match db_value {
+            DbValue::Array(values) => values.iter().map(T::from_db).collect(),
+            _ => bail!("Expected array, found {:?}", db_value),
+        }
+    }
+
+    fn to_db(&self) -> DbValue {
+        DbValue::Array(self.iter().map(DbColumnType::to_db).collect())
+    }
+
+    fn db_type() -> DbType {
+        DbType::array(T::db_type())
+    }
+}
+
+/// A column holding binary data, stored as `BYTEA` on Postgres and `BLOB` on SQLite
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Bytes(pub Vec<u8>);
+
+impl Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl DbColumnType for Bytes {
+    fn from_db(db_value: &DbValue) -> Result<Self> {
+        match db_value {
+            DbValue::Blob(b) => Ok(Self(b.clone())),
+            _ => bail!("Expected bytes, found {:?}", db_value),
+        }
+    }
+
+    fn to_db(&self) -> DbValue {
+        DbValue::Blob(self.0.clone())
+    }
+
+    fn db_type() -> DbType {
+        DbType::blob()
+    }
+}
@@ -1,11 +1,15 @@
 use std::marker::PhantomData;
 
-use crate::{cond_expr::build_condition_query, CondExpr, Database, DbValue, Pool, Table, Result};
+use crate::{
+    cond_expr::{build_condition_query, check_condition, CondExprE}, timestamp_columns, CondExpr, Database, DbValue, Pool,
+    Table, Timestamp, DbContext, QueryContext, Result
+};
 
 pub struct DeleteBuilder<'pool, Db: Database, T: Table> {
     pool: &'pool Pool<Db>,
     phantom: PhantomData<T>,
     condition: CondExpr<T>,
+    permanent: bool,
 }
 
 impl<Db: Database> Pool<Db> {
@@ -14,27 +18,66 @@ impl<Db: Database> Pool<Db> {
             pool: self,
             phantom: PhantomData,
             condition,
+            permanent: false,
         }
     }
 }
 
+impl<Db: Database, T: Table> DeleteBuilder<'_, Db, T> {
+    /// Delete the rows even if the table has a `#[sql(soft_delete)]` column
+    #[must_use]
+    pub fn permanently(mut self) -> Self {
+        self.permanent = true;
+        self
+    }
+}
+
 impl<Db: Database, T: Table> DeleteBuilder<'_, Db, T> {
     fn build_query(self) -> (String, Vec<DbValue>) {
         let mut params = vec![];
-        let mut next_param = {
-            let mut context = Default::default();
-            move || Db::param(&mut context)
+        let mut ctx = DbContext::<Db>::new(self.pool);
+        let soft_delete = if self.permanent {
+            vec![]
+        } else {
+            timestamp_columns::<T>(Timestamp::SoftDelete)
         };
+        if soft_delete.is_empty() {
+            let condition = build_condition_query(
+                self.condition.0,
+                &mut params,
+                &mut ctx,
+            );
+            let query = format!("DELETE FROM {} WHERE {}", ctx.table(T::TABLE_NAME), condition);
+            return (query, params);
+        }
+
+        let set_spec = soft_delete
+            .iter()
+            .map(|(col, now)| {
+                params.push(now.clone());
+                format!("\"{}\" = {}", col.name(), ctx.next_param())
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let not_deleted = soft_delete
+            .into_iter()
+            .map(|(col, _)| CondExprE::ColumnIsNull(col));
         let condition = build_condition_query(
-            self.condition.0,
+            CondExprE::All(std::iter::once(self.condition.0).chain(not_deleted).collect()),
             &mut params,
-            &mut next_param,
+            &mut ctx,
+        );
+        let query = format!(
+            "UPDATE {} SET {} WHERE {}",
+            ctx.table(T::TABLE_NAME),
+            set_spec,
+            condition
         );
-        let query = format!("DELETE FROM \"{}\" WHERE {}", T::TABLE_NAME, condition);
         (query, params)
     }
 
     pub async fn execute(self) -> Result<()> {
+        check_condition(&self.condition.0, Db::DIALECT)?;
         let pool = self.pool;
         let (query, params) = self.build_query();
         Db::execute(&pool.0, query, params).await?;
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    cond_expr::CondExprE,
+    db_value::DbTypeE,
+    expr::ExprE,
+    select::{column_name, sealed_select, QueryBuilder, SelectColumns},
+    CondExpr, Database, DbColumnType, DbValue, DynColumn, Ordering, Pool, Result, Table,
+};
+
+/// Columns of `T` chosen at runtime by name
+pub struct DynColumns<T: Table>(Vec<Box<dyn DynColumn<T> + Send>>);
+
+impl<T: Table> sealed_select::Sealed<T> for DynColumns<T> {}
+impl<T: Table> SelectColumns<T> for DynColumns<T> {
+    fn names(&self, read: bool) -> Vec<String> {
+        self.0.iter().map(|col| column_name(&**col, read)).collect()
+    }
+}
+
+fn column<T: Table>(name: &str) -> Result<Box<dyn DynColumn<T> + Send>> {
+    T::column(name).ok_or_else(|| anyhow!("Table {} has no column {:?}", T::TABLE_NAME, name))
+}
+
+impl<Db: Database> Pool<Db> {
+    /// Selects the columns of `T` named in `columns`, or all of them if it's empty
+    pub fn select_dyn<T: Table>(
+        &self,
+        columns: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<QueryBuilder<'_, Db, T, DynColumns<T>>> {
+        let mut columns = columns
+            .into_iter()
+            .map(|name| column::<T>(name.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        if columns.is_empty() {
+            columns = T::dyn_columns();
+        }
+        Ok(self.select_columns(DynColumns(columns)))
+    }
+}
+
+impl<Db: Database, T: Table, Columns> QueryBuilder<'_, Db, T, Columns> {
+    pub fn order_by_name(mut self, column: &str, ordering: Ordering) -> Result<Self> {
+        self.query.ordering.push((self::column::<T>(column)?, ordering, None));
+        Ok(self)
+    }
+}
+
+impl<Db: Database, T: Table> QueryBuilder<'_, Db, T, DynColumns<T>> {
+    pub async fn fetch_values(self) -> Result<Vec<Vec<DbValue>>> {
+        self.query.check(Db::DIALECT)?;
+        let pool = self.pool;
+        let types = self.query.columns.0.iter().map(|col| col.db_type()).collect();
+        let (query, params) = self.build_query();
+        Db::query_values(&pool.0, query, params, types).await
+    }
+
+    pub async fn fetch_rows(self) -> Result<Vec<Row>> {
+        let columns = self
+            .query
+            .columns
+            .0
+            .iter()
+            .map(|col| col.name())
+            .collect::<Arc<[_]>>();
+        Ok(self
+            .fetch_values()
+            .await?
+            .into_iter()
+            .map(|values| Row {
+                columns: columns.clone(),
+                values,
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    columns: Arc<[&'static str]>,
+    values: Vec<DbValue>,
+}
+
+impl Row {
+    pub fn columns(&self) -> &[&'static str] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[DbValue] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<DbValue> {
+        self.values
+    }
+
+    pub fn value(&self, column: &str) -> Option<&DbValue> {
+        self.columns
+            .iter()
+            .position(|c| *c == column)
+            .map(|idx| &self.values[idx])
+    }
+
+    pub fn get<U: DbColumnType>(&self, column: &str) -> Result<U> {
+        U::from_db(
+            self.value(column)
+                .ok_or_else(|| anyhow!("Row has no column {:?}", column))?,
+        )
+    }
+}
+
+impl<T: Table> CondExpr<T> {
+    /// A condition comparing the column named `column` to user input, parsed according to the column's type
+    ///
+    /// `op` is one of `=`, `<>`, `!=`, `<`, `<=`, `>`, `>=`, `like`, `is null` and `is not null`; `input` is ignored
+    /// for the last two.
+    pub fn filter(column: &str, op: &str, input: &str) -> Result<Self> {
+        let col = self::column::<T>(column)?;
+        let db_type = col.db_type().0;
+        let op = match op.trim().to_lowercase().as_str() {
+            "is null" | "is not null" if !matches!(db_type, DbTypeE::Nullable(_)) => {
+                bail!("Column {:?} is not nullable", column)
+            }
+            "is null" => return Ok(Self(CondExprE::ColumnIsNull(col))),
+            "is not null" => return Ok(Self(CondExprE::ColumnIsNotNull(col))),
+            "=" => "=",
+            "<>" | "!=" => "<>",
+            "<" => "<",
+            "<=" => "<=",
+            ">" => ">",
+            ">=" => ">=",
+            "like" => match db_type {
+                DbTypeE::Text => "LIKE",
+                DbTypeE::Nullable(ref t) if matches!(**t, DbTypeE::Text) => "LIKE",
+                _ => bail!("Column {:?} is not text", column),
+            },
+            op => bail!("Unsupported operator {:?}", op),
+        };
+        let value = db_type
+            .parse(input)
+            .map_err(|e| anyhow!("Invalid value {:?} for column {:?}: {}", input, column, e))?;
+        Ok(Self(CondExprE::Compare(ExprE::Column(col), op, ExprE::Value(value))))
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    marker::PhantomData,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use crate::{cond_expr::CondExprE, Column, CondExpr, DbColumnType, DbType, DbValue, DynColumn, QueryContext, Table};
+
+pub struct Expr<T: Table, U: DbColumnType>(pub(crate) ExprE<T>, PhantomData<fn() -> U>);
+
+pub(crate) enum ExprE<T: Table> {
+    Column(Box<dyn DynColumn<T> + Send>),
+    Value(DbValue),
+    Binary(Box<ExprE<T>>, &'static str, Box<ExprE<T>>),
+    #[cfg(feature = "serde")]
+    JsonPath(Box<dyn DynColumn<T> + Send>, Vec<String>),
+}
+
+impl<T: Table, U: DbColumnType> Expr<T, U> {
+    pub(crate) fn new(expr: ExprE<T>) -> Self {
+        Self(expr, PhantomData)
+    }
+
+    fn binary(self, op: &'static str, other: impl IntoExpr<T, U>) -> ExprE<T> {
+        ExprE::Binary(Box::new(self.0), op, Box::new(other.into_expr().0))
+    }
+
+    fn compare(self, op: &'static str, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        CondExpr(CondExprE::Compare(self.0, op, other.into_expr().0))
+    }
+
+    pub fn equals(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare("=", other)
+    }
+
+    pub fn not_equals(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare("<>", other)
+    }
+
+    pub fn gt(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare(">", other)
+    }
+
+    pub fn ge(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare(">=", other)
+    }
+
+    pub fn lt(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare("<", other)
+    }
+
+    pub fn le(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.compare("<=", other)
+    }
+}
+
+impl<T: Table, U: Textual> Expr<T, U> {
+    #[must_use]
+    pub fn concat(self, other: impl IntoExpr<T, U>) -> Self {
+        Self::new(self.binary("||", other))
+    }
+}
+
+impl<T: Table, U: DbColumnType, V> Column<T, U, V> {
+    pub fn equals_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().equals(other)
+    }
+
+    pub fn not_equals_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().not_equals(other)
+    }
+
+    pub fn gt_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().gt(other)
+    }
+
+    pub fn ge_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().ge(other)
+    }
+
+    pub fn lt_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().lt(other)
+    }
+
+    pub fn le_col(self, other: impl IntoExpr<T, U>) -> CondExpr<T> {
+        self.into_expr().le(other)
+    }
+}
+
+impl<T: Table, U: Textual, V> Column<T, U, V> {
+    pub fn concat(self, other: impl IntoExpr<T, U>) -> Expr<T, U> {
+        self.into_expr().concat(other)
+    }
+}
+
+pub trait IntoExpr<T: Table, U: DbColumnType> {
+    fn into_expr(self) -> Expr<T, U>;
+}
+
+impl<T: Table, U: DbColumnType> IntoExpr<T, U> for Expr<T, U> {
+    fn into_expr(self) -> Expr<T, U> {
+        self
+    }
+}
+
+impl<T: Table, U: DbColumnType, V> IntoExpr<T, U> for Column<T, U, V> {
+    fn into_expr(self) -> Expr<T, U> {
+        Expr::new(ExprE::Column(Box::new(self)))
+    }
+}
+
+impl<T: Table, U: DbColumnType> IntoExpr<T, U> for U {
+    fn into_expr(self) -> Expr<T, U> {
+        Expr::new(ExprE::Value(self.to_db()))
+    }
+}
+
+pub trait Numeric: DbColumnType {}
+pub trait Textual: DbColumnType {}
+
+impl Numeric for u8 {}
+impl Numeric for i8 {}
+impl Numeric for u16 {}
+impl Numeric for i16 {}
+impl Numeric for u32 {}
+impl Numeric for i32 {}
+impl Numeric for i64 {}
+impl Numeric for f32 {}
+impl Numeric for f64 {}
+impl<T: Numeric> Numeric for Option<T> {}
+
+impl Textual for String {}
+impl<T: Textual> Textual for Option<T> {}
+
+macro_rules! arithmetic {
+    ($($trait:ident $method:ident $op:literal)*) => {
+        $(
+            impl<T: Table, U: Numeric, R: IntoExpr<T, U>> $trait<R> for Expr<T, U> {
+                type Output = Expr<T, U>;
+
+                fn $method(self, rhs: R) -> Self::Output {
+                    Expr::new(self.binary($op, rhs))
+                }
+            }
+
+            impl<T: Table, U: Numeric, V, R: IntoExpr<T, U>> $trait<R> for Column<T, U, V> {
+                type Output = Expr<T, U>;
+
+                fn $method(self, rhs: R) -> Self::Output {
+                    Expr::new(self.into_expr().binary($op, rhs))
+                }
+            }
+        )*
+    };
+}
+
+arithmetic!(Add add "+" Sub sub "-" Mul mul "*" Div div "/");
+
+/// Builds `expr`, whose values are of a column of `db_type` unless it compares them with another column
+pub(crate) fn build_expr<T: Table>(
+    expr: ExprE<T>,
+    db_type: Option<&DbType>,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> String {
+    match expr {
+        ExprE::Column(col) => format!("\"{}\"", col.name()),
+        ExprE::Value(val) => {
+            params.push(val);
+            match db_type {
+                Some(db_type) => ctx.typed_param(db_type),
+                None => ctx.next_param(),
+            }
+        }
+        ExprE::Binary(lhs, op, rhs) => {
+            let (lhs, rhs) = build_operands(*lhs, *rhs, db_type, params, ctx);
+            format!("({} {} {})", lhs, op, rhs)
+        }
+        #[cfg(feature = "serde")]
+        ExprE::JsonPath(col, path) => crate::json::build_path::<T>(col.name(), path, params, ctx),
+    }
+}
+
+/// Builds both sides of an operator, giving values on one side the type of a column on the other
+pub(crate) fn build_operands<T: Table>(
+    lhs: ExprE<T>,
+    rhs: ExprE<T>,
+    db_type: Option<&DbType>,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> (String, String) {
+    let column_type = |expr: &ExprE<T>| match expr {
+        ExprE::Column(col) => Some(col.db_type()),
+        _ => db_type.cloned(),
+    };
+    let (lhs_type, rhs_type) = (column_type(&rhs), column_type(&lhs));
+    let lhs = build_expr(lhs, lhs_type.as_ref(), params, ctx);
+    let rhs = build_expr(rhs, rhs_type.as_ref(), params, ctx);
+    (lhs, rhs)
+}
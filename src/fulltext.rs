@@ -0,0 +1,206 @@
+use anyhow::bail;
+
+use crate::{
+    cond_expr::CondExprE,
+    db_value::DbTypeE,
+    select::QueryBuilder,
+    qualified_table, Column, CondExpr, Database, DbValue, Dialect, DynColumn, QueryContext, Result, Table,
+    Textual,
+};
+
+/// The Postgres text search configuration for `#[sql(fulltext)]` columns
+const CONFIG: &str = "english";
+
+impl<T: Table, U: Textual, V> Column<T, U, V> {
+    /// Rows whose `#[sql(fulltext)]` column contains all the words of `query`
+    ///
+    /// Running a query with this condition on another column fails.
+    pub fn matches(self, query: &str) -> CondExpr<T> {
+        CondExpr(CondExprE::Matches(Box::new(self), query.to_owned()))
+    }
+}
+
+impl<Db: Database, T: Table, Columns> QueryBuilder<'_, Db, T, Columns> {
+    /// Orders rows by the relevance of their `#[sql(fulltext)]` column to `query`, best first, before any `order_by`
+    /// columns
+    ///
+    /// Running the query fails if `column` is not `#[sql(fulltext)]`.
+    #[must_use]
+    pub fn order_by_rank<U: Textual, V>(mut self, column: Column<T, U, V>, query: &str) -> Self {
+        self.query.rank = Some((Box::new(column), query.to_owned()));
+        self
+    }
+}
+
+pub(crate) fn check_fulltext<T: Table>(column: &dyn DynColumn<T>) -> Result<()> {
+    if !column.fulltext() {
+        bail!("Column {} is not #[sql(fulltext)]", column.name());
+    }
+    Ok(())
+}
+
+/// The words of `query` as an FTS5 query matching all of them, like Postgres' `plainto_tsquery`
+fn fts5_query(query: &str) -> Option<String> {
+    let words = query
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+pub(crate) fn build_matches<T: Table>(
+    column: &dyn DynColumn<T>,
+    query: String,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> String {
+    match ctx.dialect() {
+        Dialect::Postgres => {
+            params.push(DbValue::Text(query));
+            format!(
+                "\"{}_tsvector\" @@ plainto_tsquery('{}', {})",
+                column.name(),
+                CONFIG,
+                ctx.next_param()
+            )
+        }
+        Dialect::Sqlite => match fts5_query(&query) {
+            Some(query) => {
+                params.push(DbValue::Text(query));
+                format!(
+                    "{}.rowid IN (SELECT rowid FROM {} WHERE \"{}\" MATCH {})",
+                    ctx.table(T::TABLE_NAME),
+                    ctx.table(&format!("{}_fts", T::TABLE_NAME)),
+                    column.name(),
+                    ctx.next_param(),
+                )
+            }
+            None => "FALSE".to_owned(),
+        },
+    }
+}
+
+/// An `ORDER BY` term putting the best matches first, if `query` has any words
+pub(crate) fn build_rank<T: Table>(
+    column: &dyn DynColumn<T>,
+    query: String,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> Option<String> {
+    match ctx.dialect() {
+        Dialect::Postgres => {
+            params.push(DbValue::Text(query));
+            Some(format!(
+                "ts_rank(\"{}_tsvector\", plainto_tsquery('{}', {})) DESC",
+                column.name(),
+                CONFIG,
+                ctx.next_param()
+            ))
+        }
+        // `bm25` is negative, lower for better matches
+        Dialect::Sqlite => fts5_query(&query).map(|query| {
+            params.push(DbValue::Text(query));
+            format!(
+                "COALESCE((SELECT bm25({fts}) FROM {fts} WHERE \"{}\" MATCH {} AND rowid = {}.rowid), 0) ASC",
+                column.name(),
+                ctx.next_param(),
+                ctx.table(T::TABLE_NAME),
+                fts = ctx.table(&format!("{}_fts", T::TABLE_NAME)),
+            )
+        }),
+    }
+}
+
+/// Extra column definitions for `CREATE TABLE`, and statements to run after it, indexing `T`'s
+/// `#[sql(fulltext)]` columns
+///
+/// On SQLite the FTS5 table refers to rows by rowid, which `VACUUM` may change for tables without an
+/// `INTEGER PRIMARY KEY`.
+pub(crate) fn definitions<T: Table>(
+    dialect: Dialect,
+    schema: Option<&str>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let columns = T::dyn_columns()
+        .into_iter()
+        .filter(|col| col.fulltext())
+        .collect::<Vec<_>>();
+    for col in &columns {
+        match col.db_type().0 {
+            DbTypeE::Text => {}
+            DbTypeE::Nullable(t) if matches!(*t, DbTypeE::Text) => {}
+            _ => bail!("#[sql(fulltext)] column {} is not text", col.name()),
+        }
+    }
+    if columns.is_empty() {
+        return Ok((vec![], vec![]));
+    }
+
+    let table = T::TABLE_NAME;
+    Ok(match dialect {
+        Dialect::Postgres => columns
+            .iter()
+            .map(|col| {
+                (
+                    format!(
+                        "\"{col}_tsvector\" tsvector GENERATED ALWAYS AS \
+                         (to_tsvector('{}', coalesce(\"{col}\", ''))) STORED",
+                        CONFIG,
+                        col = col.name()
+                    ),
+                    format!(
+                        "CREATE INDEX IF NOT EXISTS \"{table}_{col}_tsvector\" ON {} USING GIN (\"{col}_tsvector\")",
+                        qualified_table(schema, table),
+                        table = table,
+                        col = col.name()
+                    ),
+                )
+            })
+            .unzip(),
+        Dialect::Sqlite => {
+            let names = |prefix: &str| {
+                columns
+                    .iter()
+                    .map(|col| format!("{}\"{}\"", prefix, col.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let insert = format!(
+                "INSERT INTO \"{table}_fts\"(rowid, {}) VALUES (new.rowid, {});",
+                names(""),
+                names("new."),
+                table = table
+            );
+            let delete = format!(
+                "INSERT INTO \"{table}_fts\"(\"{table}_fts\", rowid, {}) VALUES ('delete', old.rowid, {});",
+                names(""),
+                names("old."),
+                table = table
+            );
+            let trigger = |event: &str, body: &str| {
+                format!(
+                    "CREATE TRIGGER IF NOT EXISTS \"{table}_fts_{}\" AFTER {} ON \"{table}\" BEGIN {} END",
+                    event.to_lowercase(),
+                    event,
+                    body,
+                    table = table
+                )
+            };
+            (
+                vec![],
+                vec![
+                    format!(
+                        "CREATE VIRTUAL TABLE IF NOT EXISTS \"{table}_fts\" USING fts5({}, content='{}', \
+                         tokenize='porter unicode61')",
+                        names(""),
+                        table.replace('\'', "''"),
+                        table = table
+                    ),
+                    trigger("INSERT", &insert),
+                    trigger("DELETE", &delete),
+                    trigger("UPDATE", &format!("{} {}", delete, insert)),
+                ],
+            )
+        }
+    })
+}
@@ -1,15 +1,20 @@
 use std::marker::PhantomData;
 
-use crate::{ColumnTuple, Database, DbValue, Pool, Table, Result};
+use crate::{
+    timestamp_columns, ColumnTuple, Database, DbValue, Insertable, Pool, Table, Timestamp, DbContext, QueryContext, Result
+};
 
 pub struct InsertBuilder<'pool, Db: Database, T: Table, Columns: ColumnTuple<T>> {
     pool: &'pool Pool<Db>,
     phantom: PhantomData<T>,
     columns: Columns,
-    values: Vec<Columns::Out>,
+    values: Vec<Vec<DbValue>>,
+    keep_timestamps: bool,
 }
 
 impl<Db: Database> Pool<Db> {
+    /// Inserts rows of `columns`, with `#[sql(created_at)]` and `#[sql(updated_at)]` columns set to the current time
+    /// unless [`keep_timestamps`](InsertBuilder::keep_timestamps) is called
     pub fn insert_into<T: Table, Columns: ColumnTuple<T>>(
         &self,
         columns: Columns,
@@ -19,13 +24,41 @@ impl<Db: Database> Pool<Db> {
             pool: self,
             columns,
             values: vec![],
+            keep_timestamps: false,
         }
     }
+
+    pub fn insert<T: Insertable>(&self, row: &T) -> InsertBuilder<'_, Db, T, T::Columns> {
+        self.insert_into(T::COLUMNS).row_values(row.values())
+    }
+
+    pub fn insert_many<'a, T: Insertable>(
+        &self,
+        rows: impl IntoIterator<Item = &'a T>,
+    ) -> InsertBuilder<'_, Db, T, T::Columns> {
+        rows.into_iter()
+            .fold(self.insert_into(T::COLUMNS), |builder, row| builder.row_values(row.values()))
+    }
 }
 
 impl<T: Table, Db: Database, Columns: ColumnTuple<T>> InsertBuilder<'_, Db, T, Columns> {
     #[must_use]
     pub fn values(mut self, values: Columns::Out) -> Self {
+        self.values.push(self.columns.to_values(values));
+        self
+    }
+
+    /// Insert the given values of `#[sql(created_at)]` and `#[sql(updated_at)]` columns, such as for backfills,
+    /// rather than the current time
+    ///
+    /// Those columns are still set to the current time if they aren't given.
+    #[must_use]
+    pub fn keep_timestamps(mut self) -> Self {
+        self.keep_timestamps = true;
+        self
+    }
+
+    fn row_values(mut self, values: Vec<DbValue>) -> Self {
         self.values.push(values);
         self
     }
@@ -33,41 +66,56 @@ impl<T: Table, Db: Database, Columns: ColumnTuple<T>> InsertBuilder<'_, Db, T, C
 
 impl<T: Table, Db: Database, Columns: ColumnTuple<T>> InsertBuilder<'_, Db, T, Columns> {
     fn build_query(self) -> (String, Vec<DbValue>) {
-        let columns = {
-            let mut names = Vec::with_capacity(Columns::N);
-            self.columns
-                .apply_columns(|col| names.push(format!("\"{}\"", col.name())));
-            names
+        let (mut columns, mut types) = {
+            let (mut names, mut types) = (Vec::with_capacity(Columns::N), Vec::with_capacity(Columns::N));
+            self.columns.apply_columns(|col| {
+                names.push(format!("\"{}\"", col.name()));
+                types.push(col.db_type());
+            });
+            (names, types)
         };
+        let mut rows = self.values;
+        let timestamps = timestamp_columns::<T>(Timestamp::CreatedAt)
+            .into_iter()
+            .chain(timestamp_columns::<T>(Timestamp::UpdatedAt));
+        for (col, now) in timestamps {
+            let name = format!("\"{}\"", col.name());
+            match columns.iter().position(|c| *c == name) {
+                Some(_) if self.keep_timestamps => {}
+                Some(idx) => rows.iter_mut().for_each(|row| row[idx] = now.clone()),
+                None => {
+                    columns.push(name);
+                    types.push(col.db_type());
+                    rows.iter_mut().for_each(|row| row.push(now.clone()));
+                }
+            }
+        }
 
-        let mut next_param = {
-            let mut context = Default::default();
-            move || Db::param(&mut context)
-        };
+        let mut ctx = DbContext::<Db>::new(self.pool);
         let query = format!(
-            "INSERT INTO \"{}\"({}) VALUES {}",
-            T::TABLE_NAME,
+            "INSERT INTO {}({}) VALUES {}",
+            ctx.table(T::TABLE_NAME),
             columns.join(", "),
-            (0..self.values.len())
+            rows.iter()
                 .map(|_| format!(
                     "({})",
-                    (0..Columns::N)
-                        .map(|_| next_param())
+                    types
+                        .iter()
+                        .map(|db_type| ctx.typed_param(db_type))
                         .collect::<Vec<_>>()
                         .join(", ")
                 ))
                 .collect::<Vec<_>>()
                 .join(", ")
         );
-        let params = self.values
-            .into_iter()
-            .flat_map(|values| self.columns.to_values(values)).collect();
-        (query, params)
+        (query, rows.into_iter().flatten().collect())
     }
     
     pub async fn execute(self) -> Result<()> {
+        if self.values.is_empty() { return Ok(()) }
         let pool = self.pool;
         let (query, params) = self.build_query();
-        Db::execute(&pool.0, query, params).await
+        Db::execute(&pool.0, query, params).await?;
+        Ok(())
     }
 }
@@ -0,0 +1,217 @@
+use std::ops::{Deref, DerefMut};
+
+use anyhow::bail;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    cond_expr::CondExprE,
+    db_value::{DbType, DbTypeE},
+    expr::{Expr, ExprE},
+    Column, CondExpr, DbColumnType, DbValue, Dialect, QueryContext, Result, Table,
+};
+
+/// A column holding `T` as JSON, stored as `JSONB` on Postgres and `TEXT` on SQLite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Json<T>(pub T);
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for Json<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + 'static> DbColumnType for Json<T> {
+    fn from_db(db_value: &DbValue) -> Result<Self> {
+        match db_value {
+            DbValue::Text(t) => Ok(Self(serde_json::from_str(t)?)),
+            _ => bail!("Expected JSON, found {:?}", db_value),
+        }
+    }
+
+    fn to_db(&self) -> DbValue {
+        DbValue::Text(serde_json::to_string(&self.0).expect("Failed to serialize JSON column"))
+    }
+
+    fn db_type() -> DbType {
+        DbType(DbTypeE::Json)
+    }
+}
+
+impl<T: Table, J, V> Column<T, Json<J>, V>
+where
+    Json<J>: DbColumnType,
+{
+    /// The value at `path` as text, or NULL if there is none
+    ///
+    /// Keys that are numbers index into arrays.
+    pub fn path<S: Into<String>>(self, path: impl IntoIterator<Item = S>) -> Expr<T, String> {
+        Expr::new(ExprE::JsonPath(
+            Box::new(self),
+            path.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// The column contains `value`, as with Postgres' `@>`: objects contain the given keys with matching values,
+    /// arrays contain the given elements in any order, and scalars are equal
+    pub fn contains(self, value: &impl Serialize) -> CondExpr<T> {
+        let value = serde_json::to_value(value).expect("Failed to serialize JSON value");
+        CondExpr(CondExprE::JsonContains(Box::new(self), value))
+    }
+}
+
+pub(crate) fn build_path<T: Table>(
+    column: &str,
+    path: Vec<String>,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> String {
+    match ctx.dialect() {
+        Dialect::Postgres => {
+            params.push(DbValue::Array(path.into_iter().map(DbValue::Text).collect()));
+            format!("(\"{}\" #>> {})", column, ctx.next_param())
+        }
+        Dialect::Sqlite => {
+            // Keys are matched with `json_each` rather than a JSON path, which can't hold every key
+            let last = path.len().saturating_sub(1);
+            let target = format!("{}.\"{}\"", ctx.table(T::TABLE_NAME), column);
+            let value = path.into_iter().enumerate().fold(target, |document, (idx, key)| {
+                let key = match key.parse::<i64>() {
+                    Ok(index) => DbValue::Integer(index),
+                    Err(_) => DbValue::Text(key),
+                };
+                // Only containers can be looked into further, so other values end the path
+                let containers = if idx < last { " AND type IN ('object', 'array')" } else { "" };
+                format!(
+                    "(SELECT value FROM json_each({}) WHERE key = {}{})",
+                    document,
+                    param(params, ctx, key),
+                    containers
+                )
+            });
+            format!("CAST({} AS TEXT)", value)
+        }
+    }
+}
+
+pub(crate) fn build_contains<T: Table>(
+    column: &str,
+    value: Value,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+) -> String {
+    match ctx.dialect() {
+        Dialect::Postgres => {
+            params.push(DbValue::Text(value.to_string()));
+            format!("\"{}\" @> {}", column, ctx.next_param())
+        }
+        Dialect::Sqlite => {
+            let target = format!("{}.\"{}\"", ctx.table(T::TABLE_NAME), column);
+            sqlite_contains(&target, value, params, ctx, &mut 0)
+        }
+    }
+}
+
+/// SQLite has no containment operator, so each part of `value` is checked with `json_type` and `json_each` on the
+/// JSON text `target`
+fn sqlite_contains(
+    target: &str,
+    value: Value,
+    params: &mut Vec<DbValue>,
+    ctx: &mut dyn QueryContext,
+    aliases: &mut usize,
+) -> String {
+    let (kind, entries) = match value {
+        Value::Object(entries) => ("object", entries.into_iter().map(|(key, value)| (Some(key), value)).collect()),
+        Value::Array(elements) => ("array", elements.into_iter().map(|element| (None, element)).collect::<Vec<_>>()),
+        scalar => return scalar_matches(Scalar::Document(target), scalar, params, ctx),
+    };
+    let mut conditions = vec![format!("json_type({}) = '{}'", target, kind)];
+    for (key, value) in entries {
+        let alias = format!("e{}", aliases);
+        *aliases += 1;
+        // Object members are found by key, while array elements may be anywhere
+        let key = match key {
+            Some(key) => format!("{}.key = {} AND ", alias, param(params, ctx, DbValue::Text(key))),
+            None => String::new(),
+        };
+        let matches = match value {
+            // `json_each` gives scalars as SQL values rather than JSON, so only containers can be inspected
+            Value::Object(_) | Value::Array(_) => {
+                let kind = if value.is_object() { "object" } else { "array" };
+                let value_target = format!("{}.value", alias);
+                format!(
+                    "CASE WHEN {}.type = '{}' THEN {} ELSE FALSE END",
+                    alias,
+                    kind,
+                    sqlite_contains(&value_target, value, params, ctx, aliases)
+                )
+            }
+            scalar => scalar_matches(Scalar::Element(&alias), scalar, params, ctx),
+        };
+        conditions.push(format!("EXISTS (SELECT 1 FROM json_each({}) AS {} WHERE {}{})", target, alias, key, matches));
+    }
+    format!("({})", conditions.join(" AND "))
+}
+
+fn param(params: &mut Vec<DbValue>, ctx: &mut dyn QueryContext, value: DbValue) -> String {
+    params.push(value);
+    ctx.next_param()
+}
+
+/// A scalar in a JSON document, or an element from `json_each`
+enum Scalar<'a> {
+    Document(&'a str),
+    Element(&'a str),
+}
+
+impl Scalar<'_> {
+    fn type_expr(&self) -> String {
+        match self {
+            Scalar::Document(target) => format!("json_type({})", target),
+            Scalar::Element(alias) => format!("{}.type", alias),
+        }
+    }
+
+    fn value_expr(&self) -> String {
+        match self {
+            Scalar::Document(target) => format!("json_extract({}, '$')", target),
+            Scalar::Element(alias) => format!("{}.value", alias),
+        }
+    }
+}
+
+fn scalar_matches(scalar: Scalar, value: Value, params: &mut Vec<DbValue>, ctx: &mut dyn QueryContext) -> String {
+    let type_expr = scalar.type_expr();
+    let value = match value {
+        Value::Null => return format!("{} = 'null'", type_expr),
+        Value::Bool(b) => return format!("{} = '{}'", type_expr, b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => DbValue::Integer(i),
+            None => DbValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => DbValue::Text(s),
+        Value::Object(_) | Value::Array(_) => unreachable!(),
+    };
+    let kinds = if matches!(value, DbValue::Text(_)) {
+        "'text'"
+    } else {
+        "'integer', 'real'"
+    };
+    let value_expr = scalar.value_expr();
+    format!("({} IN ({}) AND {} = {})", type_expr, kinds, value_expr, param(params, ctx, value))
+}
@@ -1,9 +1,12 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
 
 pub use anyhow::Result;
 
 use async_trait::async_trait;
-pub use derive::Table;
+pub use derive::{sql, DbColumnType, DbEnum, Embeddable, FromRow, Table};
+
+#[doc(hidden)]
+pub use anyhow::anyhow as __anyhow;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum Ordering {
@@ -11,11 +14,21 @@ pub enum Ordering {
     Descending,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
 mod sqlite;
-use db_value::DbType;
-pub use sqlite::Sqlite;
+pub use db_value::{Bytes, DbType};
+pub use sqlite::{Sqlite, SqliteConnection};
+mod changes;
+pub use changes::{Change, ChangeOp};
 mod postgres;
-pub use postgres::Postgres;
+pub use postgres::{Postgres, PostgresConnection};
+mod listen;
+pub use listen::Notification;
 
 mod db_value;
 pub use db_value::DbValue;
@@ -26,13 +39,25 @@ pub trait Database {
 
     type Context: Default;
 
+    #[doc(hidden)]
+    const DIALECT: Dialect;
+
     fn param(ctx: &mut Self::Context) -> String;
 
+    fn type_name(db_type: &DbType) -> String {
+        db_type.name()
+    }
+
+    /// Statements creating the types a column of `db_type` needs, run before `CREATE TABLE`
+    fn type_definitions(_db_type: &DbType) -> Vec<String> {
+        vec![]
+    }
+
     async fn execute(
         connection: &Self::Connection,
         query: String,
         params: Vec<DbValue>,
-    ) -> Result<()>;
+    ) -> Result<u64>;
 
     async fn query<T: Table, Columns: ColumnTuple<T>, U: From<Columns::Out>>(
         connection: &Self::Connection,
@@ -42,13 +67,84 @@ pub trait Database {
     ) -> Result<Vec<U>>
     where
         U: Send + 'static;
+
+    async fn query_values(
+        connection: &Self::Connection,
+        query: String,
+        params: Vec<DbValue>,
+        types: Vec<DbType>,
+    ) -> Result<Vec<Vec<DbValue>>>;
+
+    /// The number of connections currently checked out of the pool, or waited for
+    #[doc(hidden)]
+    fn in_use(connection: &Self::Connection) -> usize;
+}
+
+/// SQL syntax that differs between databases beyond parameter placeholders
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
 }
 
-pub struct Pool<Db: Database>(Db::Connection);
+/// State threaded through query building
+pub(crate) trait QueryContext {
+    fn next_param(&mut self) -> String;
+    fn dialect(&self) -> Dialect;
+    /// `name` quoted, and qualified by the pool's schema if it has one
+    fn table(&self, name: &str) -> String;
+
+    /// The next parameter, holding a value of a column of `db_type`
+    fn typed_param(&mut self, db_type: &DbType) -> String {
+        let param = self.next_param();
+        db_type.write_param(param, self.dialect())
+    }
+}
+
+pub(crate) struct DbContext<Db: Database>(Db::Context, Option<Arc<str>>);
+
+impl<Db: Database> DbContext<Db> {
+    pub(crate) fn new(pool: &Pool<Db>) -> Self {
+        Self(Default::default(), pool.1.clone())
+    }
+}
+
+impl<Db: Database> QueryContext for DbContext<Db> {
+    fn next_param(&mut self) -> String {
+        Db::param(&mut self.0)
+    }
+
+    fn dialect(&self) -> Dialect {
+        Db::DIALECT
+    }
+
+    fn table(&self, name: &str) -> String {
+        qualified_table(self.1.as_deref(), name)
+    }
+}
+
+/// Fails if a query's result has `found` columns, where `expected` were to be read, as raw queries may
+pub(crate) fn check_column_count(found: usize, expected: usize) -> Result<()> {
+    if found != expected {
+        anyhow::bail!("Query returns {} columns, but {} were expected", found, expected);
+    }
+    Ok(())
+}
+
+pub(crate) fn qualified_table(schema: Option<&str>, name: &str) -> String {
+    match schema {
+        Some(schema) => format!("\"{}\".\"{}\"", schema.replace('"', "\"\""), name),
+        None => format!("\"{}\"", name),
+    }
+}
+
+/// A connection pool, along with the schema its tables are in (see `Pool::<Postgres>::for_tenant`)
+pub struct Pool<Db: Database>(Db::Connection, Option<Arc<str>>);
 
 impl<Db: Database> Clone for Pool<Db> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0.clone(), self.1.clone())
     }
 }
 
@@ -58,7 +154,11 @@ impl<Db: Database> Pool<Db> {
     }
 
     pub fn from_connection(connection: Db::Connection) -> Self {
-        Self(connection)
+        Self(connection, None)
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.1.as_deref()
     }
 }
 
@@ -66,6 +166,48 @@ pub trait Table: 'static + Sized {
     const TABLE_NAME: &'static str;
     type Columns: ColumnTuple<Self>;
     const COLUMNS: Self::Columns;
+
+    fn dyn_columns() -> Vec<Box<dyn DynColumn<Self> + Send>> {
+        let mut columns = Vec::with_capacity(<Self::Columns as ColumnTuple<Self>>::N);
+        Self::COLUMNS.apply_columns(|col| columns.push(col.boxed()));
+        columns
+    }
+
+    fn column(name: &str) -> Option<Box<dyn DynColumn<Self> + Send>> {
+        Self::dyn_columns().into_iter().find(|col| col.name() == name)
+    }
+}
+
+pub trait FromRow: From<<Self::Columns as ColumnTuple<Self::Table>>::Out> {
+    type Table: Table;
+    type Columns: ColumnTuple<Self::Table>;
+    const COLUMNS: Self::Columns;
+}
+
+/// A group of columns stored inline in a table's row by `#[sql(flatten)]`, with names prefixed by `P`
+pub trait Embeddable<T: Table, P: Prefix>: Sized + From<<Self::Columns as ColumnTuple<T>>::Out> {
+    type Columns: ColumnTuple<T>;
+    const COLUMNS: Self::Columns;
+
+    /// The database values of the group's columns
+    fn values(&self) -> Vec<DbValue>;
+}
+
+pub trait Prefix: 'static {
+    const PREFIX: &'static str;
+}
+
+/// A row that can be written whole, implemented by `#[derive(Table)]`
+///
+/// Fields converted with `#[sql(db_as(..))]` are cloned to be written, so tables with any implement this only when
+/// marked `#[sql(insertable)]`.
+pub trait Insertable: Table {
+    /// The database values of the row's columns, in the order of `COLUMNS`
+    fn values(&self) -> Vec<DbValue>;
+}
+
+pub trait PrimaryKey: Insertable {
+    fn primary_key(&self) -> CondExpr<Self>;
 }
 
 pub trait DbColumnType: 'static + Sized {
@@ -77,18 +219,31 @@ pub trait DbColumnType: 'static + Sized {
 
 pub mod create_table;
 pub mod delete;
+pub mod dynamic;
 pub mod insert;
+pub mod raw;
+pub mod replicated;
+pub mod tenant;
 pub mod select;
 pub mod update;
 
 mod sealed_dyncolumn {
     pub trait Sealed {}
+
+    pub trait Boxed<T: crate::Table> {
+        fn boxed(&self) -> Box<dyn crate::DynColumn<T> + Send>;
+    }
 }
-pub trait DynColumn<T: Table>: sealed_dyncolumn::Sealed {
+pub trait DynColumn<T: Table>: sealed_dyncolumn::Sealed + sealed_dyncolumn::Boxed<T> {
     fn name(&self) -> &'static str;
     fn db_type(&self) -> DbType;
-    fn foreign_key(&self) -> Option<String>;
+    /// The `REFERENCES` clause of the column, with the referenced table in `schema`
+    fn foreign_key(&self, schema: Option<&str>) -> Option<String>;
     fn unique(&self) -> bool;
+    fn primary_key(&self) -> bool;
+    fn version(&self) -> bool;
+    fn fulltext(&self) -> bool;
+    fn timestamp(&self) -> Option<(Timestamp, DbValue)>;
 }
 
 pub trait ForeignKey<U: DbColumnType, V>: sealed_dyncolumn::Sealed {
@@ -105,7 +260,11 @@ pub struct Column<T: Table, U: DbColumnType, V: 'static> {
     name: &'static str,
     foreign_key: ForeignKeySpec<U, V>,
     unique: bool,
+    primary_key: bool,
     conversion: Conversion<U, V>,
+    timestamp: Option<(Timestamp, fn() -> U)>,
+    version: bool,
+    fulltext: bool,
 }
 
 impl<T, U, V> Clone for Column<T, U, V>
@@ -139,21 +298,36 @@ where
     T: Table,
     U: DbColumnType,
 {
+    #[allow(clippy::too_many_arguments)]
     pub const fn new(
         name: &'static str,
         foreign_key: ForeignKeySpec<U, V>,
         unique: bool,
+        primary_key: bool,
         conversion: Conversion<U, V>,
+        timestamp: Option<(Timestamp, fn() -> U)>,
+        version: bool,
+        fulltext: bool,
     ) -> Self {
         Self {
             phantom: PhantomData,
             name,
             foreign_key,
             unique,
+            primary_key,
             conversion,
+            timestamp,
+            version,
+            fulltext,
         }
     }
 
+    /// This column under another name, such as the prefixed name of a column of an [`Embeddable`] group
+    pub const fn renamed(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
     pub fn to_db(&self, value: V) -> DbValue {
         self.conversion.0(value).to_db()
     }
@@ -164,6 +338,11 @@ where
 }
 
 impl<T: Table, U: DbColumnType, V> sealed_dyncolumn::Sealed for Column<T, U, V> {}
+impl<T: Table, U: DbColumnType, V> sealed_dyncolumn::Boxed<T> for Column<T, U, V> {
+    fn boxed(&self) -> Box<dyn DynColumn<T> + Send> {
+        Box::new(*self)
+    }
+}
 impl<T: Table, U: DbColumnType, V> DynColumn<T> for Column<T, U, V> {
     fn name(&self) -> &'static str {
         self.name
@@ -173,11 +352,12 @@ impl<T: Table, U: DbColumnType, V> DynColumn<T> for Column<T, U, V> {
         U::db_type()
     }
 
-    fn foreign_key(&self) -> Option<String> {
+    fn foreign_key(&self, schema: Option<&str>) -> Option<String> {
         self.foreign_key.map(|(k, on_update, on_delete)| {
             format!(
-                "REFERENCES {} ON UPDATE {} ON DELETE {}",
-                k.spec(),
+                "REFERENCES {}(\"{}\") ON UPDATE {} ON DELETE {}",
+                qualified_table(schema, k.table()),
+                k.column(),
                 on_update.unwrap_or("NO ACTION"),
                 on_delete.unwrap_or("NO ACTION")
             )
@@ -187,6 +367,56 @@ impl<T: Table, U: DbColumnType, V> DynColumn<T> for Column<T, U, V> {
     fn unique(&self) -> bool {
         self.unique
     }
+
+    fn primary_key(&self) -> bool {
+        self.primary_key
+    }
+
+    fn version(&self) -> bool {
+        self.version
+    }
+
+    fn fulltext(&self) -> bool {
+        self.fulltext
+    }
+
+    fn timestamp(&self) -> Option<(Timestamp, DbValue)> {
+        self.timestamp.map(|(kind, now)| (kind, now().to_db()))
+    }
+}
+
+#[doc(hidden)]
+pub const MAX_PREFIXED_NAME: usize = 128;
+
+/// Concatenates `prefix` and `name` at compile time, as the names of [`Embeddable`] columns depend on a generic
+/// [`Prefix`]
+#[doc(hidden)]
+pub const fn __concat_name(prefix: &str, name: &str) -> ([u8; MAX_PREFIXED_NAME], usize) {
+    let (prefix, name) = (prefix.as_bytes(), name.as_bytes());
+    assert!(
+        prefix.len() + name.len() <= MAX_PREFIXED_NAME,
+        "Prefixed column name is too long"
+    );
+    let mut buf = [0; MAX_PREFIXED_NAME];
+    let mut i = 0;
+    while i < prefix.len() {
+        buf[i] = prefix[i];
+        i += 1;
+    }
+    let mut j = 0;
+    while j < name.len() {
+        buf[i + j] = name[j];
+        j += 1;
+    }
+    (buf, i + j)
+}
+
+#[doc(hidden)]
+pub const fn __name_str(name: &'static ([u8; MAX_PREFIXED_NAME], usize)) -> &'static str {
+    match std::str::from_utf8(name.0.split_at(name.1).0) {
+        Ok(name) => name,
+        Err(_) => panic!("Prefixed column name is not UTF-8"),
+    }
 }
 
 impl<T: Table, U: DbColumnType, V> ForeignKey<U, V> for Column<T, U, V> {
@@ -206,3 +436,19 @@ pub use cond_expr::CondExpr;
 
 mod column_tuple;
 pub use column_tuple::ColumnTuple;
+
+mod related;
+
+mod timestamp;
+pub(crate) use timestamp::timestamp_columns;
+pub use timestamp::{Now, Timestamp};
+
+#[cfg(feature = "serde")]
+mod json;
+#[cfg(feature = "serde")]
+pub use json::Json;
+
+mod fulltext;
+
+mod expr;
+pub use expr::{Expr, IntoExpr, Numeric, Textual};
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use futures::{stream, Stream, StreamExt};
+use tokio::{sync::mpsc::UnboundedSender, task::JoinHandle};
+use tokio_postgres::{AsyncMessage, Client, Config, NoTls};
+
+use crate::{Pool, Postgres, Result};
+
+pub use tokio_postgres::Notification;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+impl Pool<Postgres> {
+    /// Notifications sent to `channel`, received on a dedicated connection outside the pool
+    ///
+    /// The connection is reopened whenever it's lost, and notifications sent while reconnecting are missed. It's
+    /// closed once the stream is dropped.
+    pub async fn listen(&self, channel: &str) -> Result<impl Stream<Item = Notification>> {
+        let config = self.0.config().clone();
+        let channel = channel.to_owned();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut subscription = subscribe(&config, &channel, &tx).await?;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => return,
+                    _ = &mut subscription.1 => {}
+                }
+                let mut delay = Duration::from_millis(100);
+                subscription = loop {
+                    tokio::select! {
+                        _ = tx.closed() => return,
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                    match subscribe(&config, &channel, &tx).await {
+                        Ok(subscription) => break subscription,
+                        Err(_) => delay = (delay * 2).min(MAX_RECONNECT_DELAY),
+                    }
+                };
+            }
+        });
+        Ok(stream::poll_fn(move |cx| rx.poll_recv(cx)))
+    }
+
+    pub async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.raw_execute("SELECT pg_notify(?, ?)", vec![channel.into(), payload.into()])
+            .await
+    }
+}
+
+/// Connects and listens on `channel`, returning the client along with the task forwarding notifications to `tx`,
+/// which ends when the connection does
+async fn subscribe(
+    config: &Config,
+    channel: &str,
+    tx: &UnboundedSender<Notification>,
+) -> Result<(Client, JoinHandle<()>)> {
+    let (client, mut connection) = config.connect(NoTls).await?;
+    let tx = tx.clone();
+    let forward = tokio::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(Ok(message)) = messages.next().await {
+            if let AsyncMessage::Notification(notification) = message {
+                if tx.send(notification).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    client
+        .batch_execute(&format!("LISTEN \"{}\"", channel.replace('"', "\"\"")))
+        .await?;
+    Ok((client, forward))
+}
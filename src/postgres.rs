@@ -1,38 +1,99 @@
-use std::{error::Error, str::FromStr};
+use std::{error::Error, ops::Deref, str::FromStr};
 
 use crate::{
-    db_value::{DbType, DbTypeE},
-    ColumnTuple, Database, DbValue, Pool, Result, Table,
+    db_value::{strip_not_null, DbType, DbTypeE},
+    check_column_count, ColumnTuple, Database, DbValue, Dialect, Pool, Result, Table,
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
 use deadpool_postgres::{Manager, ManagerConfig};
-use deadpool_sqlite::rusqlite::types::Value;
 use futures::{StreamExt, TryStreamExt};
 use tokio_postgres::{
-    types::{FromSql, Type},
+    types::{FromSql, Kind, Type},
     Config, NoTls,
 };
 
 pub struct Postgres;
+
+/// The connection pool of a `Pool<Postgres>`, along with the configuration used for connections outside the pool
+#[derive(Clone)]
+pub struct PostgresConnection {
+    pool: deadpool::managed::Pool<Manager>,
+    config: Config,
+}
+
+impl PostgresConnection {
+    /// `config` should be the one `pool` connects with
+    pub fn new(pool: deadpool::managed::Pool<Manager>, config: Config) -> Self {
+        Self { pool, config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn into_pool(self) -> deadpool::managed::Pool<Manager> {
+        self.pool
+    }
+}
+
+impl Deref for PostgresConnection {
+    type Target = deadpool::managed::Pool<Manager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
 #[async_trait]
 impl Database for Postgres {
-    type Connection = deadpool::managed::Pool<deadpool_postgres::Manager>;
+    type Connection = PostgresConnection;
 
     type Context = usize;
 
+    const DIALECT: Dialect = Dialect::Postgres;
+
     fn param(ctx: &mut Self::Context) -> String {
         *ctx += 1;
         format!("${}", *ctx)
     }
 
+    fn type_name(db_type: &DbType) -> String {
+        match &db_type.0 {
+            DbTypeE::Enum(name, _) => format!("\"{}\" NOT NULL", name),
+            DbTypeE::Blob => "BYTEA NOT NULL".to_owned(),
+            #[cfg(feature = "serde")]
+            DbTypeE::Json => "JSONB NOT NULL".to_owned(),
+            DbTypeE::Array(t) => format!("{}[] NOT NULL", strip_not_null(Self::type_name(&DbType((**t).clone())))),
+            DbTypeE::Nullable(t) => strip_not_null(Self::type_name(&DbType((**t).clone()))),
+            _ => db_type.name(),
+        }
+    }
+
+    fn type_definitions(db_type: &DbType) -> Vec<String> {
+        let (name, variants) = match &db_type.0 {
+            DbTypeE::Enum(name, variants) => (name, variants),
+            DbTypeE::Nullable(t) | DbTypeE::Array(t) => return Self::type_definitions(&DbType((**t).clone())),
+            _ => return vec![],
+        };
+        // Postgres has no `CREATE TYPE IF NOT EXISTS`, and several tables may share an enum
+        vec![format!(
+            "DO $$ BEGIN CREATE TYPE \"{}\" AS ENUM ({}); EXCEPTION WHEN duplicate_object THEN NULL; END $$",
+            name,
+            variants
+                .iter()
+                .map(|v| format!("'{}'", v.replace('\'', "''")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )]
+    }
+
     async fn execute(
         connection: &Self::Connection,
         query: String,
         params: Vec<DbValue>,
-    ) -> Result<()> {
-        connection.get().await?.execute_raw(&query, params).await?;
-        Ok(())
+    ) -> Result<u64> {
+        Ok(connection.get().await?.execute_raw(&query, params).await?)
     }
 
     async fn query<T, Columns, U>(
@@ -55,36 +116,74 @@ impl Database for Postgres {
             .map(|row| {
                 let mut n = 0usize;
                 let row = row?;
+                check_column_count(row.len(), Columns::N)?;
                 columns.try_from_values(|db_type: DbType| {
                     n += 1;
-                    DbValue::from_postgres_value(row.get(n - 1), db_type).unwrap()
+                    DbValue::from_postgres_value(row.try_get(n - 1)?, db_type)
                 })
             })
             .map(|r| r.map(U::from))
             .try_collect()
             .await?)
     }
+
+    async fn query_values(
+        connection: &Self::Connection,
+        query: String,
+        params: Vec<DbValue>,
+        types: Vec<DbType>,
+    ) -> Result<Vec<Vec<DbValue>>> {
+        connection
+            .get()
+            .await?
+            .query_raw(&query, params)
+            .await?
+            .map(|row| {
+                let row = row?;
+                check_column_count(row.len(), types.len())?;
+                types
+                    .iter()
+                    .enumerate()
+                    .map(|(n, db_type)| DbValue::from_postgres_value(row.try_get(n)?, db_type.clone()))
+                    .collect()
+            })
+            .try_collect()
+            .await
+    }
+
+    fn in_use(connection: &Self::Connection) -> usize {
+        let status = connection.status();
+        (status.size as isize - status.available).max(0) as usize
+    }
 }
 
 impl Pool<Postgres> {
     pub async fn connect(connection_string: impl AsRef<str>) -> Result<Self> {
+        let config = Config::from_str(connection_string.as_ref())?;
         let pool = deadpool::managed::Pool::builder(Manager::from_config(
-            Config::from_str(connection_string.as_ref())?,
+            config.clone(),
             NoTls,
             ManagerConfig {
                 recycling_method: deadpool_postgres::RecyclingMethod::Fast,
             },
         ))
         .build()?;
-        Ok(Self(pool))
+        Ok(Self(PostgresConnection::new(pool, config), None))
+    }
+
+    /// A pool sharing this one's connections, with its tables in the schema `tenant`
+    ///
+    /// `create` creates the schema if needed. Queries from `sql!` are qualified too, but `raw_query` and `raw_execute`
+    /// run as written.
+    pub fn for_tenant(&self, tenant: &str) -> Self {
+        Self(self.0.clone(), Some(tenant.into()))
     }
 }
 
 mod to_sql {
     use std::error::Error;
 
-    use deadpool_sqlite::rusqlite::types::Value;
-    use tokio_postgres::types::{private::BytesMut, to_sql_checked, IsNull, ToSql, Type};
+    use tokio_postgres::types::{private::BytesMut, to_sql_checked, IsNull, Kind, ToSql, Type};
 
     use crate::DbValue;
 
@@ -94,22 +193,58 @@ mod to_sql {
             ty: &Type,
             out: &mut BytesMut,
         ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
-            match &self.0 {
-                Value::Null => Ok(IsNull::Yes),
-                Value::Integer(i) => i.to_sql(ty, out),
-                Value::Real(r) => r.to_sql(ty, out),
-                Value::Text(t) => t.to_sql(ty, out),
-                Value::Blob(b) => b.to_sql(ty, out),
+            match self {
+                DbValue::Null => Ok(IsNull::Yes),
+                DbValue::Integer(i) => match *ty {
+                    Type::INT2 => i16::try_from(*i)?.to_sql(ty, out),
+                    Type::INT4 => i32::try_from(*i)?.to_sql(ty, out),
+                    _ => i.to_sql(ty, out),
+                },
+                DbValue::Real(r) if *ty == Type::FLOAT4 => (*r as f32).to_sql(ty, out),
+                DbValue::Real(r) => r.to_sql(ty, out),
+                // The binary format of enums is their label
+                DbValue::Text(t) if matches!(ty.kind(), Kind::Enum(_)) => {
+                    out.extend_from_slice(t.as_bytes());
+                    Ok(IsNull::No)
+                }
+                // The binary format of JSONB is a version number followed by the text
+                DbValue::Text(t) if *ty == Type::JSONB => {
+                    out.extend_from_slice(&[1]);
+                    out.extend_from_slice(t.as_bytes());
+                    Ok(IsNull::No)
+                }
+                DbValue::Text(t) if *ty == Type::JSON => {
+                    out.extend_from_slice(t.as_bytes());
+                    Ok(IsNull::No)
+                }
+                DbValue::Text(t) => t.to_sql(ty, out),
+                DbValue::Blob(b) => b.to_sql(ty, out),
+                DbValue::Array(values) => values.to_sql(ty, out),
             }
         }
 
         fn accepts(ty: &Type) -> bool {
-            i64::accepts(ty) || f64::accepts(ty) || String::accepts(ty)
+            super::accepts(ty)
         }
 
         to_sql_checked!();
     }
 }
+
+fn accepts(ty: &Type) -> bool {
+    i64::accepts(ty)
+        || i32::accepts(ty)
+        || i16::accepts(ty)
+        || f64::accepts(ty)
+        || f32::accepts(ty)
+        || String::accepts(ty)
+        || Vec::<u8>::accepts(ty)
+        || matches!(ty.kind(), Kind::Enum(_))
+        || *ty == Type::JSON
+        || *ty == Type::JSONB
+        || matches!(ty.kind(), Kind::Array(member) if accepts(member))
+}
+
 struct PostgresValue<'a>(Type, Option<&'a [u8]>);
 
 impl<'a> FromSql<'a> for PostgresValue<'a> {
@@ -118,7 +253,7 @@ impl<'a> FromSql<'a> for PostgresValue<'a> {
     }
 
     fn accepts(ty: &Type) -> bool {
-        i64::accepts(ty) || f64::accepts(ty) || String::accepts(ty)
+        accepts(ty)
     }
 
     fn from_sql_null(ty: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
@@ -137,17 +272,49 @@ impl DbValue {
     fn from_postgres_value(val: PostgresValue<'_>, db_type: DbType) -> super::Result<Self> {
         fn from_raw(pg_ty: Type, val: Option<&[u8]>, ty: &DbTypeE) -> super::Result<DbValue> {
             match (val, ty) {
-                (None, DbTypeE::Nullable(_)) => Ok(DbValue(Value::Null)),
-                (Some(raw), &DbTypeE::Nullable(ref t)) => from_raw(pg_ty, Some(raw), &*t),
-                (Some(raw), &DbTypeE::Integer) if i64::accepts(&pg_ty) => Ok(DbValue(
-                    Value::Integer(i64::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?),
+                (None, DbTypeE::Nullable(_)) => Ok(DbValue::Null),
+                (Some(raw), DbTypeE::Nullable(t)) => from_raw(pg_ty, Some(raw), t),
+                (val, DbTypeE::Custom(_, t)) => from_raw(pg_ty, val, t),
+                (Some(raw), &DbTypeE::Integer) if i64::accepts(&pg_ty) => Ok(DbValue::Integer(
+                    i64::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?,
+                )),
+                (Some(raw), &DbTypeE::Integer) if i32::accepts(&pg_ty) => Ok(DbValue::Integer(
+                    i32::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?.into(),
+                )),
+                (Some(raw), &DbTypeE::Integer) if i16::accepts(&pg_ty) => Ok(DbValue::Integer(
+                    i16::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?.into(),
                 )),
-                (Some(raw), &DbTypeE::Text) if String::accepts(&pg_ty) => Ok(DbValue(Value::Text(
+                (Some(raw), &DbTypeE::Text | &DbTypeE::Enum(..)) if String::accepts(&pg_ty) => Ok(DbValue::Text(
                     String::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?,
-                ))),
-                (Some(raw), &DbTypeE::Real) if f64::accepts(&pg_ty) => Ok(DbValue(Value::Real(
+                )),
+                (Some(raw), &DbTypeE::Text | &DbTypeE::Enum(..)) if matches!(pg_ty.kind(), Kind::Enum(_)) => {
+                    Ok(DbValue::Text(std::str::from_utf8(raw)?.to_owned()))
+                }
+                (Some(raw), &DbTypeE::Real) if f64::accepts(&pg_ty) => Ok(DbValue::Real(
                     f64::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?,
-                ))),
+                )),
+                (Some(raw), &DbTypeE::Real) if f32::accepts(&pg_ty) => Ok(DbValue::Real(
+                    f32::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?.into(),
+                )),
+                (Some(raw), &DbTypeE::Blob) if Vec::<u8>::accepts(&pg_ty) => Ok(DbValue::Blob(
+                    Vec::<u8>::from_sql(&pg_ty, raw).map_err(|e| anyhow!(e))?,
+                )),
+                (Some(raw), DbTypeE::Array(t)) if matches!(pg_ty.kind(), Kind::Array(_)) => Ok(DbValue::Array(
+                    Vec::<PostgresValue>::from_sql(&pg_ty, raw)
+                        .map_err(|e| anyhow!(e))?
+                        .into_iter()
+                        .map(|value| from_raw(value.0, value.1, t))
+                        .collect::<super::Result<_>>()?,
+                )),
+                #[cfg(feature = "serde")]
+                (Some(raw), &DbTypeE::Json) if pg_ty == Type::JSONB => match raw.split_first() {
+                    Some((1, json)) => Ok(DbValue::Text(std::str::from_utf8(json)?.to_owned())),
+                    _ => Err(anyhow!("Unsupported JSONB version")),
+                },
+                #[cfg(feature = "serde")]
+                (Some(raw), &DbTypeE::Json) if pg_ty == Type::JSON || String::accepts(&pg_ty) => {
+                    Ok(DbValue::Text(std::str::from_utf8(raw)?.to_owned()))
+                }
                 (None, _) => Err(anyhow!(
                     "Unexpected null for type {:?} (PG type {})",
                     ty,
@@ -0,0 +1,162 @@
+use std::marker::PhantomData;
+
+use anyhow::bail;
+
+use crate::{ColumnTuple, Database, DbContext, DbValue, Pool, QueryContext, Result, Table};
+
+pub struct RawQuery<'pool, Db: Database> {
+    pool: &'pool Pool<Db>,
+    query: String,
+    params: Vec<DbValue>,
+}
+
+impl<Db: Database> Pool<Db> {
+    /// A query written in SQL, with a `?` in place of each of `params` and `??` for a literal `?`
+    ///
+    /// Table names aren't qualified with the pool's [`schema`](Pool::schema).
+    pub fn raw_query(
+        &self,
+        query: impl Into<String>,
+        params: impl IntoIterator<Item = DbValue>,
+    ) -> RawQuery<'_, Db> {
+        RawQuery {
+            pool: self,
+            query: query.into(),
+            params: params.into_iter().collect(),
+        }
+    }
+
+    pub async fn raw_execute(
+        &self,
+        query: impl Into<String>,
+        params: impl IntoIterator<Item = DbValue>,
+    ) -> Result<()> {
+        let (query, params) = self.raw_query(query, params).build_query()?;
+        Db::execute(&self.0, query, params).await?;
+        Ok(())
+    }
+}
+
+impl<Db: Database> RawQuery<'_, Db> {
+    fn build_query(self) -> Result<(String, Vec<DbValue>)> {
+        let placeholders = count_params(&self.query);
+        if placeholders != self.params.len() {
+            bail!(
+                "Query has {} placeholders, but {} parameters were given",
+                placeholders,
+                self.params.len()
+            );
+        }
+        let mut ctx = DbContext::<Db>::new(self.pool);
+        Ok((translate_params(&self.query, &mut ctx), self.params))
+    }
+
+    pub async fn fetch_all<T: Table, Columns: ColumnTuple<T>, U: From<Columns::Out> + Send + 'static>(
+        self,
+        columns: Columns,
+    ) -> Result<Vec<U>> {
+        let pool = self.pool;
+        let (query, params) = self.build_query()?;
+        Db::query::<T, Columns, U>(&pool.0, columns, query, params).await
+    }
+}
+
+/// The SQL of a [`TypedQuery`], given a function quoting table names
+type RenderQuery = fn(&dyn Fn(&str) -> String) -> String;
+
+/// A query checked at compile time by [`sql!`](crate::sql)
+pub struct TypedQuery<T: Table, Columns: ColumnTuple<T>> {
+    query: RenderQuery,
+    params: Vec<DbValue>,
+    columns: Columns,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Table, Columns: ColumnTuple<T>> TypedQuery<T, Columns> {
+    #[doc(hidden)]
+    pub fn new(query: RenderQuery, params: Vec<DbValue>, columns: Columns) -> Self {
+        Self {
+            query,
+            params,
+            columns,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The query as it runs on `pool`, with its tables in the pool's schema
+    pub fn query<Db: Database>(&self, pool: &Pool<Db>) -> String {
+        let ctx = DbContext::<Db>::new(pool);
+        (self.query)(&|name| ctx.table(name))
+    }
+
+    pub async fn fetch_all<U: From<Columns::Out> + Send + 'static>(
+        self,
+        pool: &Pool<impl Database>,
+    ) -> Result<Vec<U>> {
+        pool.raw_query(self.query(pool), self.params)
+            .fetch_all(self.columns)
+            .await
+    }
+}
+
+#[doc(hidden)]
+pub fn column_list<T: Table, Columns: ColumnTuple<T>>(columns: &Columns, qualifier: Option<&str>) -> String {
+    let mut names = Vec::with_capacity(Columns::N);
+    columns.apply_columns(|col| match qualifier {
+        Some(qualifier) => names.push(format!("{}.\"{}\"", qualifier, col.name())),
+        None => names.push(format!("\"{}\"", col.name())),
+    });
+    names.join(", ")
+}
+
+enum Placeholder {
+    Param,
+    /// `??`, standing for a literal `?` such as in Postgres' JSONB operators
+    Escaped,
+}
+
+/// The `?`s outside of string literals and quoted identifiers
+fn placeholders(query: &str) -> Vec<(usize, Placeholder)> {
+    let mut found = vec![];
+    let mut quote = None;
+    let mut chars = query.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '?') if chars.next_if(|&(_, c)| c == '?').is_some() => found.push((idx, Placeholder::Escaped)),
+            (None, '?') => found.push((idx, Placeholder::Param)),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    found
+}
+
+pub(crate) fn count_params(query: &str) -> usize {
+    placeholders(query)
+        .into_iter()
+        .filter(|(_, placeholder)| matches!(placeholder, Placeholder::Param))
+        .count()
+}
+
+/// Replaces each `?` outside of string literals and quoted identifiers with the database's parameter syntax, and
+/// each `??` with `?`
+pub(crate) fn translate_params(query: &str, ctx: &mut dyn QueryContext) -> String {
+    let mut translated = String::with_capacity(query.len());
+    let mut last = 0;
+    for (idx, placeholder) in placeholders(query) {
+        translated.push_str(&query[last..idx]);
+        match placeholder {
+            Placeholder::Param => {
+                translated.push_str(&ctx.next_param());
+                last = idx + 1;
+            }
+            Placeholder::Escaped => {
+                translated.push('?');
+                last = idx + 2;
+            }
+        }
+    }
+    translated.push_str(&query[last..]);
+    translated
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    cond_expr::CondExprE, Column, ColumnTuple, CondExpr, Database, DbColumnType, DbValue, Insertable,
+    Pool, Result, Table,
+};
+
+impl<Db: Database> Pool<Db> {
+    /// Loads the rows referencing each of `parents` through `foreign_key` with a single query, in the order of
+    /// `parents`
+    pub async fn load_related<P, C, U, V>(
+        &self,
+        parents: &[P],
+        foreign_key: Column<C, U, V>,
+    ) -> Result<Vec<Vec<C>>>
+    where
+        P: Insertable,
+        C: Insertable + From<<C::Columns as ColumnTuple<C>>::Out> + Send,
+        U: DbColumnType,
+    {
+        let (referenced, _, _) = foreign_key
+            .foreign_key
+            .ok_or_else(|| anyhow!("Column {} is not a foreign key", foreign_key.name))?;
+        if referenced.table() != P::TABLE_NAME {
+            bail!(
+                "Column {} references {}, not {}",
+                foreign_key.name,
+                referenced.table(),
+                P::TABLE_NAME
+            );
+        }
+        if parents.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let key_index = column_index::<P>(referenced.column())?;
+        let keys = parents
+            .iter()
+            .map(|parent| parent.values().swap_remove(key_index))
+            .collect::<Vec<_>>();
+        let children = self
+            .select(C::COLUMNS)
+            .r#where(CondExpr(CondExprE::ColumnIn(Box::new(foreign_key), keys.clone())))
+            .fetch_all::<C>()
+            .await?;
+
+        let foreign_key_index = column_index::<C>(foreign_key.name)?;
+        let mut by_key = HashMap::<_, Vec<C>>::new();
+        for child in children {
+            let key = child.values().swap_remove(foreign_key_index);
+            by_key.entry(value_key(&key)).or_default().push(child);
+        }
+        // Parents may repeat, so all but the last with a key get copies of its children
+        let mut remaining = HashMap::<_, usize>::new();
+        for key in &keys {
+            *remaining.entry(value_key(key)).or_default() += 1;
+        }
+        keys.iter()
+            .map(|key| {
+                let key = value_key(key);
+                let count = remaining.get_mut(&key).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    return Ok(by_key.remove(&key).unwrap_or_default());
+                }
+                // Copies are rebuilt from the child's values, as rows needn't be `Clone`
+                by_key
+                    .get(&key)
+                    .map_or(&[][..], Vec::as_slice)
+                    .iter()
+                    .map(|child| {
+                        let mut values = child.values().into_iter();
+                        C::COLUMNS
+                            .try_from_values(|_| Ok(values.next().unwrap()))
+                            .map(C::from)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn column_index<T: Table>(name: &str) -> Result<usize> {
+    let mut names = vec![];
+    T::COLUMNS.apply_columns(|col| names.push(col.name()));
+    names
+        .iter()
+        .position(|n| *n == name)
+        .ok_or_else(|| anyhow!("Table {} has no column {}", T::TABLE_NAME, name))
+}
+
+// `DbValue` can hold floats, so it has no `Hash` implementation
+fn value_key(value: &DbValue) -> String {
+    format!("{:?}", value)
+}
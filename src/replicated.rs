@@ -0,0 +1,133 @@
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    dynamic::DynColumns, raw::RawQuery, select::QueryBuilder, Column, ColumnTuple, CondExpr, Database, DbColumnType,
+    DbValue, Insertable, Pool, Result, Table,
+};
+
+/// How a [`ReplicatedPool`] picks the replica each read runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    RoundRobin,
+    /// The replica with the fewest connections in use
+    LeastBusy,
+}
+
+/// A primary pool with read replicas
+///
+/// `select`, `select_dyn`, `exists`, `raw_query` and `load_related` run on a replica. Everything else, reached through
+/// `Deref`, runs on the primary, including `raw_execute`, the accessors generated by `#[sql(belongs_to)]` and
+/// `#[sql(has_many)]`, and queries from [`sql!`](crate::sql) given the `ReplicatedPool`; pass them `replica()` to read
+/// from a replica instead. Replicas may lag behind the primary, so reads that must see earlier writes should go
+/// through `on_primary`.
+pub struct ReplicatedPool<Db: Database> {
+    primary: Pool<Db>,
+    replicas: Arc<[Pool<Db>]>,
+    balancing: Balancing,
+    next: Arc<AtomicUsize>,
+}
+
+impl<Db: Database> Clone for ReplicatedPool<Db> {
+    fn clone(&self) -> Self {
+        Self {
+            primary: self.primary.clone(),
+            replicas: self.replicas.clone(),
+            balancing: self.balancing,
+            next: self.next.clone(),
+        }
+    }
+}
+
+impl<Db: Database> ReplicatedPool<Db> {
+    /// Reads are balanced round-robin, and run on the primary if there are no `replicas`
+    pub fn new(primary: Pool<Db>, replicas: impl IntoIterator<Item = Pool<Db>>) -> Self {
+        Self {
+            primary,
+            replicas: replicas.into_iter().collect(),
+            balancing: Balancing::RoundRobin,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[must_use]
+    pub fn balancing(mut self, balancing: Balancing) -> Self {
+        self.balancing = balancing;
+        self
+    }
+
+    pub fn on_primary(&self) -> &Pool<Db> {
+        &self.primary
+    }
+
+    /// The pool the next read runs on
+    pub fn replica(&self) -> &Pool<Db> {
+        match self.balancing {
+            _ if self.replicas.is_empty() => &self.primary,
+            Balancing::RoundRobin => {
+                &self.replicas[self.next.fetch_add(1, Ordering::Relaxed) % self.replicas.len()]
+            }
+            Balancing::LeastBusy => {
+                // Start at a rotating offset so idle replicas share the load
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..self.replicas.len())
+                    .map(|i| &self.replicas[(start + i) % self.replicas.len()])
+                    .min_by_key(|pool| Db::in_use(&pool.0))
+                    .unwrap()
+            }
+        }
+    }
+
+    pub fn select<T: Table, Columns: ColumnTuple<T>>(
+        &self,
+        columns: Columns,
+    ) -> QueryBuilder<'_, Db, T, Columns> {
+        self.replica().select(columns)
+    }
+
+    pub fn select_dyn<T: Table>(
+        &self,
+        columns: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<QueryBuilder<'_, Db, T, DynColumns<T>>> {
+        self.replica().select_dyn(columns)
+    }
+
+    pub async fn exists<T: Table>(&self, condition: CondExpr<T>) -> Result<bool> {
+        self.replica().exists(condition).await
+    }
+
+    /// A read written in SQL, as with [`Pool::raw_query`]; writes go through `raw_execute` on the primary
+    pub fn raw_query(
+        &self,
+        query: impl Into<String>,
+        params: impl IntoIterator<Item = DbValue>,
+    ) -> RawQuery<'_, Db> {
+        self.replica().raw_query(query, params)
+    }
+
+    pub async fn load_related<P, C, U, V>(
+        &self,
+        parents: &[P],
+        foreign_key: Column<C, U, V>,
+    ) -> Result<Vec<Vec<C>>>
+    where
+        P: Insertable,
+        C: Insertable + From<<C::Columns as ColumnTuple<C>>::Out> + Send,
+        U: DbColumnType,
+    {
+        self.replica().load_related(parents, foreign_key).await
+    }
+}
+
+impl<Db: Database> Deref for ReplicatedPool<Db> {
+    type Target = Pool<Db>;
+
+    fn deref(&self) -> &Pool<Db> {
+        &self.primary
+    }
+}
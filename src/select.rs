@@ -1,20 +1,38 @@
 use crate::{
-    column_tuple::ColumnTuple, Column, CondExpr, Database, DbColumnType, DbValue,
-    DynColumn, Ordering, Pool, Table, build_condition_query, Result
+    column_tuple::ColumnTuple, cond_expr::{check_condition, CondExprE, Correlation}, db_value::{DbType, DbTypeE}, expr::ExprE,
+    Column, CondExpr, Database, DbColumnType, DbValue, DynColumn, Nulls, Ordering, Pool, Postgres, Table,
+    Timestamp, build_condition_query, timestamp_columns, DbContext, Dialect, QueryContext, Result
 };
-use std::marker::PhantomData;
+use anyhow::bail;
 
 
 
 pub struct QueryBuilder<'pool, Db: Database, T: Table, Columns> {
-    pool: &'pool Pool<Db>,
-    table: PhantomData<T>,
-    columns: Columns,
+    pub(crate) pool: &'pool Pool<Db>,
+    pub(crate) query: Select<T, Columns>,
+}
+
+pub(crate) struct Select<T: Table, Columns> {
+    pub(crate) columns: Columns,
     condition: CondExpr<T>,
     limit: Option<usize>,
-    ordering: Option<(Box<dyn DynColumn<T> + 'pool + Send>, Ordering)>,
+    offset: Option<usize>,
+    pub(crate) ordering: Vec<OrderingSpec<T>>,
+    pub(crate) rank: Option<(Box<dyn DynColumn<T> + Send>, String)>,
+    cursor: Option<Vec<DbValue>>,
+    distinct: Distinct<T>,
+    with_deleted: bool,
+    pub(crate) correlation: Option<Correlation>,
+}
+
+enum Distinct<T: Table> {
+    All,
+    Unique,
+    On(Vec<Box<dyn DynColumn<T> + Send>>),
 }
 
+type OrderingSpec<T> = (Box<dyn DynColumn<T> + Send>, Ordering, Option<Nulls>);
+
 impl<Db: Database> Pool<Db> {
     pub fn select<T, Columns: ColumnTuple<T>>(
         &self,
@@ -23,94 +41,326 @@ impl<Db: Database> Pool<Db> {
     where
         T: Table,
     {
+        self.select_columns(columns)
+    }
+
+    pub(crate) fn select_columns<T: Table, Columns>(&self, columns: Columns) -> QueryBuilder<'_, Db, T, Columns> {
         QueryBuilder {
-            table: PhantomData,
             pool: self,
-            columns,
-            condition: CondExpr::TRUE,
-            limit: None,
-            ordering: None,
+            query: Select {
+                columns,
+                condition: CondExpr::TRUE,
+                limit: None,
+                offset: None,
+                ordering: vec![],
+                rank: None,
+                cursor: None,
+                distinct: Distinct::All,
+                with_deleted: false,
+                correlation: None,
+            },
         }
     }
+
+    pub async fn exists<T: Table>(&self, condition: CondExpr<T>) -> Result<bool> {
+        self.select(T::COLUMNS).r#where(condition).exists().await
+    }
 }
 
-impl<T: Table, Db: Database, Columns: ColumnTuple<T>> QueryBuilder<'_, Db, T, Columns> {
+impl<T: Table, Db: Database, Columns> QueryBuilder<'_, Db, T, Columns> {
     #[must_use]
-    pub fn r#where(self, condition: CondExpr<T>) -> Self {
-        Self { condition, ..self }
+    pub fn r#where(mut self, condition: CondExpr<T>) -> Self {
+        self.query.condition = condition;
+        self
     }
 
     #[must_use]
-    pub fn order_by<U: DbColumnType, V>(self, column: Column<T, U, V>, ordering: Ordering) -> Self {
-        Self {
-            ordering: Some((Box::new(column), ordering)),
-            ..self
-        }
+    pub fn order_by<U: DbColumnType, V>(mut self, column: Column<T, U, V>, ordering: Ordering) -> Self {
+        self.query.ordering.push((Box::new(column), ordering, None));
+        self
     }
 
     #[must_use]
-    pub fn limit(self, limit: impl Into<Option<usize>>) -> Self {
-        Self {
-            limit: limit.into(),
-            ..self
-        }
+    pub fn order_by_nulls<U: DbColumnType, V>(
+        mut self,
+        column: Column<T, U, V>,
+        ordering: Ordering,
+        nulls: Nulls,
+    ) -> Self {
+        self.query.ordering.push((Box::new(column), ordering, Some(nulls)));
+        self
+    }
+
+    #[must_use]
+    pub fn limit(mut self, limit: impl Into<Option<usize>>) -> Self {
+        self.query.limit = limit.into();
+        self
+    }
+
+    #[must_use]
+    pub fn offset(mut self, offset: impl Into<Option<usize>>) -> Self {
+        self.query.offset = offset.into();
+        self
+    }
+
+    #[must_use]
+    pub fn distinct(mut self) -> Self {
+        self.query.distinct = Distinct::Unique;
+        self
+    }
+
+    /// Include rows marked as deleted in the table's `#[sql(soft_delete)]` column
+    #[must_use]
+    pub fn with_deleted(mut self) -> Self {
+        self.query.with_deleted = true;
+        self
+    }
+
+    /// Only return rows ordered after `cursor`, which holds one value for each `order_by` column (usually those of the
+    /// last row of the previous page). NULLs are ordered as with `order_by_nulls`, or else as the database orders
+    /// them: last in ascending order on Postgres, and first on SQLite.
+    #[must_use]
+    pub fn paginate_after(mut self, cursor: impl IntoIterator<Item = DbValue>) -> Self {
+        self.query.cursor = Some(cursor.into_iter().collect());
+        self
     }
 }
 
-impl<T: Table, Db: Database, Columns: ColumnTuple<T>> QueryBuilder<'_, Db, T, Columns> {
-    fn build_query(
-        self,
-    ) -> (String, Vec<DbValue>) {
-        let columns = {
-            let mut names = Vec::with_capacity(Columns::N);
-            self.columns
-                .apply_columns(|col| names.push(format!("\"{}\"", col.name())));
-            names
+impl<T: Table, Columns: ColumnTuple<T>> QueryBuilder<'_, Postgres, T, Columns> {
+    #[must_use]
+    pub fn distinct_on<On: ColumnTuple<T>>(mut self, columns: On) -> Self {
+        let mut on = Vec::with_capacity(On::N);
+        columns.apply_columns(|col| on.push(col.boxed()));
+        self.query.distinct = Distinct::On(on);
+        self
+    }
+}
+
+pub(crate) mod sealed_select {
+    pub trait Sealed<T> {}
+}
+
+/// The columns a `SELECT` returns, either statically typed or chosen at runtime
+pub trait SelectColumns<T: Table>: sealed_select::Sealed<T> {
+    /// The quoted columns, cast to the types they're read as if `read`
+    fn names(&self, read: bool) -> Vec<String>;
+}
+
+impl<T: Table, Columns: ColumnTuple<T>> sealed_select::Sealed<T> for Columns {}
+impl<T: Table, Columns: ColumnTuple<T>> SelectColumns<T> for Columns {
+    fn names(&self, read: bool) -> Vec<String> {
+        let mut names = Vec::with_capacity(Columns::N);
+        self.apply_columns(|col| names.push(column_name(col, read)));
+        names
+    }
+}
+
+pub(crate) fn column_name<T: Table>(col: &dyn DynColumn<T>, read: bool) -> String {
+    let name = format!("\"{}\"", col.name());
+    match read {
+        true => col.db_type().read_column(name),
+        false => name,
+    }
+}
+
+impl<T: Table, Columns: SelectColumns<T>> Select<T, Columns> {
+    fn keyset_condition(&mut self, dialect: Dialect) -> CondExprE<T> {
+        let keys = match self.cursor.take() {
+            Some(cursor) if !cursor.is_empty() => self.ordering.iter().zip(cursor).collect::<Vec<_>>(),
+            _ => return CondExprE::True,
         };
-        let mut next_param = {
-            let mut context = Default::default();
-            move || Db::param(&mut context)
+        CondExprE::Any(
+            (0..keys.len())
+                .map(|i| {
+                    let mut conds = keys[..i]
+                        .iter()
+                        .map(|((col, _, _), val)| match val {
+                            DbValue::Null => CondExprE::ColumnIsNull(col.boxed()),
+                            val => CondExprE::ColumnEquals(col.boxed(), val.clone()),
+                        })
+                        .collect::<Vec<_>>();
+                    let ((col, dir, nulls), val) = &keys[i];
+                    let (op, ascending) = match dir {
+                        Ordering::Ascending => (">", true),
+                        Ordering::Descending => ("<", false),
+                    };
+                    let nulls_last = match nulls {
+                        Some(nulls) => *nulls == Nulls::Last,
+                        // Postgres sorts NULLs as larger than any value, and SQLite as smaller
+                        None => (dialect == Dialect::Postgres) == ascending,
+                    };
+                    conds.push(match val {
+                        DbValue::Null if nulls_last => CondExprE::False,
+                        DbValue::Null => CondExprE::ColumnIsNotNull(col.boxed()),
+                        val => {
+                            let after = CondExprE::Compare(ExprE::Column(col.boxed()), op, ExprE::Value(val.clone()));
+                            if nulls_last && matches!(col.db_type().0, DbTypeE::Nullable(_)) {
+                                CondExprE::Any(vec![after, CondExprE::ColumnIsNull(col.boxed())])
+                            } else {
+                                after
+                            }
+                        }
+                    });
+                    CondExprE::All(conds)
+                })
+                .collect(),
+        )
+    }
+
+    /// Checks the query before it runs on a `dialect` database, including its subqueries
+    pub(crate) fn check(&self, dialect: Dialect) -> Result<()> {
+        if matches!(self.distinct, Distinct::On(_)) && dialect != Dialect::Postgres {
+            bail!("DISTINCT ON is only supported on Postgres");
+        }
+        check_condition(&self.condition.0, dialect)?;
+        if matches!(&self.correlation, Some(correlation) if correlation.outer_table == T::TABLE_NAME) {
+            bail!("exists_where can't correlate a subquery on {} with the same table", T::TABLE_NAME);
+        }
+        if let Some((col, _)) = &self.rank {
+            crate::fulltext::check_fulltext(&**col)?;
+        }
+        if let Some(cursor) = &self.cursor {
+            if self.rank.is_some() {
+                bail!("Pagination cursors can't be combined with order_by_rank");
+            }
+            if cursor.len() != self.ordering.len() {
+                bail!(
+                    "Pagination cursor has {} values, but the query is ordered by {} columns",
+                    cursor.len(),
+                    self.ordering.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The query, whose columns are read into values if `read`, or otherwise used by an enclosing query
+    pub(crate) fn build(
+        mut self,
+        read: bool,
+        params: &mut Vec<DbValue>,
+        ctx: &mut dyn QueryContext,
+    ) -> String {
+        let columns = self.columns.names(read);
+        let keyset = self.keyset_condition(ctx.dialect());
+        let not_deleted = if self.with_deleted {
+            vec![]
+        } else {
+            timestamp_columns::<T>(Timestamp::SoftDelete)
+                .into_iter()
+                .map(|(col, _)| CondExprE::ColumnIsNull(col))
+                .collect()
         };
-        let mut params = vec![];
-        let condition = build_condition_query(
-            self.condition.0,
-            &mut params,
-            &mut next_param,
+        let mut condition = build_condition_query(
+            CondExprE::All([self.condition.0, keyset].into_iter().chain(not_deleted).collect()),
+            params,
+            ctx,
         );
-        let order_by = if let Some((col, dir)) = &self.ordering {
+        if let Some(correlation) = self.correlation {
+            condition = format!(
+                "{} AND {}.\"{}\" = {}.\"{}\"",
+                condition,
+                ctx.table(T::TABLE_NAME),
+                correlation.column,
+                ctx.table(correlation.outer_table),
+                correlation.outer_column
+            );
+        }
+        let rank = self
+            .rank
+            .and_then(|(col, query)| crate::fulltext::build_rank(&*col, query, params, ctx));
+        let order_by = if self.ordering.is_empty() && rank.is_none() {
+            String::new()
+        } else {
             format!(
-                "ORDER BY \"{}\" {}",
-                col.name(),
-                match dir {
-                    Ordering::Ascending => "ASC",
-                    Ordering::Descending => "DESC",
-                }
+                "ORDER BY {}",
+                rank.into_iter()
+                    .chain(self.ordering.iter().map(|(col, dir, nulls)| format!(
+                        "\"{}\" {}{}",
+                        col.name(),
+                        match dir {
+                            Ordering::Ascending => "ASC",
+                            Ordering::Descending => "DESC",
+                        },
+                        match nulls {
+                            Some(Nulls::First) => " NULLS FIRST",
+                            Some(Nulls::Last) => " NULLS LAST",
+                            None => "",
+                        }
+                    )))
+                    .collect::<Vec<_>>()
+                    .join(", ")
             )
-        } else {
-            String::new()
         };
 
-        let limit = if let Some(limit) = self.limit {
-            format!("LIMIT {}", limit)
-        } else {
-            String::new()
+        // SQLite only accepts OFFSET after a LIMIT, and Postgres rejects SQLite's `LIMIT -1`
+        let limit = match (self.limit, self.offset) {
+            (Some(limit), None) => format!("LIMIT {}", limit),
+            (limit, Some(offset)) => format!(
+                "LIMIT {} OFFSET {}",
+                limit.map_or(i64::MAX, |limit| limit as i64),
+                offset
+            ),
+            (None, None) => String::new(),
         };
-        let query = format!(
-            "SELECT {} FROM \"{}\" WHERE {} {} {}",
+        let distinct = match &self.distinct {
+            Distinct::All => String::new(),
+            Distinct::Unique => "DISTINCT".to_owned(),
+            Distinct::On(on) => format!(
+                "DISTINCT ON ({})",
+                on.iter()
+                    .map(|col| format!("\"{}\"", col.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        format!(
+            "SELECT {} {} FROM {} WHERE {} {} {}",
+            distinct,
             columns.join(", "),
-            T::TABLE_NAME,
+            ctx.table(T::TABLE_NAME),
             condition,
             order_by,
             limit
-        );
+        )
+    }
+}
 
-        (query, params)
+impl<T: Table, Db: Database, Columns: ColumnTuple<T>> QueryBuilder<'_, Db, T, Columns> {
+    pub(crate) fn into_select(self) -> Select<T, Columns> {
+        self.query
     }
 
     pub async fn fetch_all<U: From<Columns::Out> + Send + 'static>(self) -> Result<Vec<U>> {
+        self.query.check(Db::DIALECT)?;
         let pool = self.pool;
-        let columns = self.columns;
+        let columns = self.query.columns;
         let (query, params) = self.build_query();
         Db::query::<T, Columns, U>(&pool.0, columns, query, params).await
     }
 }
+
+impl<T: Table, Db: Database, Columns: SelectColumns<T>> QueryBuilder<'_, Db, T, Columns> {
+    pub(crate) fn build_query(
+        self,
+    ) -> (String, Vec<DbValue>) {
+        let mut ctx = DbContext::<Db>::new(self.pool);
+        let mut params = vec![];
+        let query = self.query.build(true, &mut params, &mut ctx);
+
+        (query, params)
+    }
+
+    pub async fn exists(self) -> Result<bool> {
+        self.query.check(Db::DIALECT)?;
+        let pool = self.pool;
+        let (query, params) = self.build_query();
+        // Postgres has no cast from BOOLEAN to INT8, and SQLite has no booleans
+        let query = format!(
+            "SELECT CAST(CASE WHEN EXISTS ({}) THEN 1 ELSE 0 END AS INT8)",
+            query
+        );
+        let rows = Db::query_values(&pool.0, query, params, vec![DbType(DbTypeE::Integer)]).await?;
+        Ok(i64::from_db(&rows[0][0])? != 0)
+    }
+}
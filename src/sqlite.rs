@@ -1,20 +1,60 @@
-use std::path::Path;
+use std::{ops::Deref, path::Path};
 
+use anyhow::anyhow;
 use async_trait::async_trait;
 use deadpool::managed::{Hook, HookError, HookErrorCause, Manager};
 use deadpool_sqlite::rusqlite::params_from_iter;
 use futures::FutureExt;
 
-use crate::{ColumnTuple, Database, DbValue, Pool, Result, Table};
+use crate::{
+    changes::{self, Subscribers},
+    db_value::DbType,
+    check_column_count, ColumnTuple, Database, DbValue, Dialect, Pool, Result, Table,
+};
 
 pub struct Sqlite;
 
+/// The connection pool of a `Pool<Sqlite>`, along with the subscribers to its row changes
+#[derive(Clone)]
+pub struct SqliteConnection {
+    pool: deadpool::managed::Pool<deadpool_sqlite::Manager>,
+    subscribers: Option<Subscribers>,
+}
+
+impl SqliteConnection {
+    /// Changes can't be subscribed to through pools created this way, since their connections have no update hook
+    pub fn new(pool: deadpool::managed::Pool<deadpool_sqlite::Manager>) -> Self {
+        Self {
+            pool,
+            subscribers: None,
+        }
+    }
+
+    pub(crate) fn subscribers(&self) -> Option<&Subscribers> {
+        self.subscribers.as_ref()
+    }
+
+    pub fn into_pool(self) -> deadpool::managed::Pool<deadpool_sqlite::Manager> {
+        self.pool
+    }
+}
+
+impl Deref for SqliteConnection {
+    type Target = deadpool::managed::Pool<deadpool_sqlite::Manager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
 #[async_trait]
 impl Database for Sqlite {
-    type Connection = deadpool::managed::Pool<deadpool_sqlite::Manager>;
+    type Connection = SqliteConnection;
 
     type Context = ();
 
+    const DIALECT: Dialect = Dialect::Sqlite;
+
     fn param(_: &mut Self::Context) -> String {
         "?".to_owned()
     }
@@ -23,16 +63,16 @@ impl Database for Sqlite {
         connection: &Self::Connection,
         query: String,
         params: Vec<DbValue>,
-    ) -> Result<()> {
-        connection
+    ) -> Result<u64> {
+        let rows = connection
             .get()
             .await?
             .interact(move |conn| {
-                conn.execute(&query, params_from_iter(params.into_iter().map(|d| d.0)))
+                conn.execute(&query, params_from_iter(params))
             })
             .await
-            .unwrap()?; // The unwrap unwraps an `InteractError`, only given if the closure above panics or aborts
-        Ok(())
+            .map_err(interact_error)??;
+        Ok(rows as u64)
     }
 
     async fn query<T: Table, Columns: ColumnTuple<T>, U: From<Columns::Out>>(
@@ -48,32 +88,293 @@ impl Database for Sqlite {
             .get()
             .await?
             .interact(move |conn| {
-                conn.prepare(&query)?
-                    .query_map(params_from_iter(params.into_iter().map(|d| d.0)), |row| {
-                        let mut idx: usize = 0;
-                        Ok(columns.try_from_values(|_| {
-                            idx += 1;
-                            DbValue(row.get_unwrap(idx - 1))
-                        }))
-                    })?
-                    .map(|r| r?)
-                    .map(|u| u.map(U::from))
-                    .collect::<Result<_, _>>()
+                let mut statement = conn.prepare(&query)?;
+                check_column_count(statement.column_count(), Columns::N)?;
+                let mut rows = statement.query(params_from_iter(params))?;
+                let mut values = vec![];
+                while let Some(row) = rows.next()? {
+                    let mut idx: usize = 0;
+                    values.push(U::from(columns.try_from_values(|db_type| {
+                        idx += 1;
+                        Ok(json::typed(row.get(idx - 1)?, &db_type.0))
+                    })?));
+                }
+                Ok(values)
+            })
+            .await
+            .map_err(interact_error)?
+    }
+
+    async fn query_values(
+        connection: &Self::Connection,
+        query: String,
+        params: Vec<DbValue>,
+        types: Vec<DbType>,
+    ) -> Result<Vec<Vec<DbValue>>> {
+        connection
+            .get()
+            .await?
+            .interact(move |conn| {
+                let mut statement = conn.prepare(&query)?;
+                check_column_count(statement.column_count(), types.len())?;
+                let mut rows = statement.query(params_from_iter(params))?;
+                let mut values = vec![];
+                while let Some(row) = rows.next()? {
+                    values.push(
+                        types
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, db_type)| Ok(json::typed(row.get(idx)?, &db_type.0)))
+                            .collect::<Result<_>>()?,
+                    );
+                }
+                Ok(values)
             })
             .await
-            .unwrap()
+            .map_err(interact_error)?
+    }
+
+    fn in_use(connection: &Self::Connection) -> usize {
+        let status = connection.status();
+        (status.size as isize - status.available).max(0) as usize
+    }
+}
+
+/// The error of a closure given to `interact` that panicked or was aborted
+fn interact_error(error: deadpool_sqlite::InteractError) -> anyhow::Error {
+    anyhow!("SQLite connection task failed: {}", error)
+}
+
+mod to_sql {
+    use deadpool_sqlite::rusqlite::{
+        types::{FromSql, FromSqlResult, ToSqlOutput, Value, ValueRef},
+        ToSql,
+    };
+
+    use crate::DbValue;
+
+    impl ToSql for DbValue {
+        fn to_sql(&self) -> deadpool_sqlite::rusqlite::Result<ToSqlOutput<'_>> {
+            Ok(match self {
+                DbValue::Null => ToSqlOutput::Borrowed(ValueRef::Null),
+                DbValue::Integer(i) => ToSqlOutput::Borrowed(ValueRef::Integer(*i)),
+                DbValue::Real(r) => ToSqlOutput::Borrowed(ValueRef::Real(*r)),
+                DbValue::Text(t) => ToSqlOutput::Borrowed(ValueRef::Text(t.as_bytes())),
+                DbValue::Blob(b) => ToSqlOutput::Borrowed(ValueRef::Blob(b)),
+                DbValue::Array(values) => ToSqlOutput::Owned(Value::Text(super::json::encode(values))),
+            })
+        }
+    }
+
+    impl FromSql for DbValue {
+        fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+            Ok(match Value::from(value) {
+                Value::Null => DbValue::Null,
+                Value::Integer(i) => DbValue::Integer(i),
+                Value::Real(r) => DbValue::Real(r),
+                Value::Text(t) => DbValue::Text(t),
+                Value::Blob(b) => DbValue::Blob(b),
+            })
+        }
+    }
+}
+
+/// Arrays are stored as JSON text, so `json_each` can query them
+mod json {
+    use std::{fmt::Write, iter::Peekable, str::Chars};
+
+    use crate::{db_value::DbTypeE, DbValue};
+
+    pub(super) fn encode(values: &[DbValue]) -> String {
+        let mut json = String::new();
+        encode_array(values, &mut json);
+        json
+    }
+
+    fn encode_array(values: &[DbValue], json: &mut String) {
+        json.push('[');
+        for (idx, value) in values.iter().enumerate() {
+            if idx > 0 {
+                json.push(',');
+            }
+            match value {
+                DbValue::Null => json.push_str("null"),
+                DbValue::Integer(i) => write!(json, "{}", i).unwrap(),
+                DbValue::Real(r) if r.is_finite() => write!(json, "{:?}", r).unwrap(),
+                DbValue::Real(_) => json.push_str("null"),
+                DbValue::Text(t) => encode_string(t, json),
+                DbValue::Blob(b) => {
+                    encode_string(&b.iter().map(|byte| format!("{:02x}", byte)).collect::<String>(), json)
+                }
+                DbValue::Array(values) => encode_array(values, json),
+            }
+        }
+        json.push(']');
+    }
+
+    fn encode_string(s: &str, json: &mut String) {
+        json.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+                c => json.push(c),
+            }
+        }
+        json.push('"');
+    }
+
+    /// Converts a value read from SQLite to `db_type`, decoding JSON arrays
+    ///
+    /// Values that can't be decoded are returned unchanged, for `DbColumnType::from_db` to report.
+    pub(super) fn typed(value: DbValue, db_type: &DbTypeE) -> DbValue {
+        match (value, db_type) {
+            (value, DbTypeE::Nullable(t) | DbTypeE::Custom(_, t)) => typed(value, t),
+            (DbValue::Text(json), DbTypeE::Array(_)) => {
+                let mut chars = json.chars().peekable();
+                match parse(&mut chars) {
+                    Some(value @ DbValue::Array(_)) if skip_whitespace(&mut chars).is_none() => {
+                        typed(value, db_type)
+                    }
+                    _ => DbValue::Text(json),
+                }
+            }
+            (DbValue::Array(values), DbTypeE::Array(element)) => {
+                DbValue::Array(values.into_iter().map(|v| typed_element(v, element)).collect())
+            }
+            (DbValue::Integer(i), DbTypeE::Real) => DbValue::Real(i as f64),
+            (value, _) => value,
+        }
+    }
+
+    /// Like `typed`, for an element decoded from a JSON array, which holds blobs as hex strings
+    fn typed_element(value: DbValue, db_type: &DbTypeE) -> DbValue {
+        match (value, db_type) {
+            (value, DbTypeE::Nullable(t) | DbTypeE::Custom(_, t)) => typed_element(value, t),
+            (DbValue::Text(hex), DbTypeE::Blob) => decode_hex(&hex).map_or(DbValue::Text(hex), DbValue::Blob),
+            (value, _) => typed(value, db_type),
+        }
+    }
+
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+            .collect()
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+        while chars.peek()?.is_whitespace() {
+            chars.next();
+        }
+        chars.peek().copied()
+    }
+
+    /// The four hex digits of a `\\u` escape
+    fn parse_code_unit(chars: &mut Peekable<Chars>) -> Option<u32> {
+        let code = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+        u32::from_str_radix(&code, 16).ok()
+    }
+
+    fn parse(chars: &mut Peekable<Chars>) -> Option<DbValue> {
+        match skip_whitespace(chars)? {
+            '[' => {
+                chars.next();
+                let mut values = vec![];
+                if skip_whitespace(chars)? == ']' {
+                    chars.next();
+                    return Some(DbValue::Array(values));
+                }
+                loop {
+                    values.push(parse(chars)?);
+                    match skip_whitespace(chars)? {
+                        ',' => chars.next(),
+                        ']' => {
+                            chars.next();
+                            return Some(DbValue::Array(values));
+                        }
+                        _ => return None,
+                    };
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => return Some(DbValue::Text(s)),
+                        '\\' => s.push(match chars.next()? {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            'b' => '\u{8}',
+                            'f' => '\u{c}',
+                            'u' => match parse_code_unit(chars)? {
+                                // Characters outside the BMP are escaped as a UTF-16 surrogate pair
+                                high @ 0xd800..=0xdbff => {
+                                    if (chars.next()?, chars.next()?) != ('\\', 'u') {
+                                        return None;
+                                    }
+                                    let low = parse_code_unit(chars)?;
+                                    if !(0xdc00..=0xdfff).contains(&low) {
+                                        return None;
+                                    }
+                                    char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))?
+                                }
+                                code => char::from_u32(code)?,
+                            },
+                            c => c,
+                        }),
+                        c => s.push(c),
+                    }
+                }
+            }
+            'n' => {
+                let null = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+                (null == "null").then_some(DbValue::Null)
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                    number.push(c);
+                }
+                number
+                    .parse()
+                    .map(DbValue::Integer)
+                    .or_else(|_| number.parse().map(DbValue::Real))
+                    .ok()
+            }
+        }
     }
 }
 
 impl Pool<Sqlite> {
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_subscribers(path, Subscribers::default())
+    }
+
+    /// Opens a pool whose row changes go to `subscribers`, which may outlive it
+    pub(crate) fn open_with_subscribers(path: impl AsRef<Path>, subscribers: Subscribers) -> Result<Self> {
+        let hook_subscribers = subscribers.clone();
         let pool = deadpool_sqlite::Config::new(path.as_ref())
             .builder(deadpool::Runtime::Tokio1)?
             .post_create(Hook::async_fn(
-                |conn: &mut <deadpool_sqlite::Manager as Manager>::Type, _| {
-                    async {
-                        conn.interact(|conn| conn.execute("PRAGMA foreign_keys=on;", []).unwrap())
-                            .await
+                move |conn: &mut <deadpool_sqlite::Manager as Manager>::Type, _| {
+                    let subscribers = hook_subscribers.clone();
+                    async move {
+                        conn.interact(move |conn| {
+                            conn.execute("PRAGMA foreign_keys=on;", []).unwrap();
+                            conn.update_hook(Some(move |action, db: &str, table: &str, rowid| {
+                                if db == "main" {
+                                    changes::notify(&subscribers, action, table, rowid);
+                                }
+                            }));
+                        })
+                        .await
                             .map_err(|_| {
                                 HookError::Abort(HookErrorCause::StaticMessage(
                                     "Failed to setup pragmas",
@@ -85,7 +386,13 @@ impl Pool<Sqlite> {
                 },
             ))
             .build()?;
-        Ok(Self(pool))
+        Ok(Self(
+            SqliteConnection {
+                pool,
+                subscribers: Some(subscribers),
+            },
+            None,
+        ))
     }
 
     pub fn in_memory() -> Self {
@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::bail;
+
+use crate::{changes::Subscribers, Pool, Result, Sqlite};
+
+/// An open tenant pool, with a token held by each [`TenantPool`] handed out for it
+struct OpenPool {
+    tenant: String,
+    pool: Pool<Sqlite>,
+    users: Arc<()>,
+}
+
+#[derive(Default)]
+struct Tenants {
+    /// The least recently used first
+    open: Vec<OpenPool>,
+    /// Kept apart from the pools, so change streams outlive a pool being closed and reopened
+    subscribers: HashMap<String, Subscribers>,
+}
+
+/// Per-tenant SQLite databases, opened on first use
+///
+/// On Postgres, tenants with their own schema share a pool through `Pool::<Postgres>::for_tenant`.
+pub struct TenantPools {
+    path: Arc<dyn Fn(&str) -> PathBuf + Send + Sync>,
+    max_open: usize,
+    tenants: Arc<Mutex<Tenants>>,
+}
+
+impl Clone for TenantPools {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            max_open: self.max_open,
+            tenants: self.tenants.clone(),
+        }
+    }
+}
+
+impl TenantPools {
+    /// `path` gives the database file of each tenant, and must not let tenant ids escape the intended directory. At
+    /// most `max_open` pools are open, closing the least recently used one that's not in use when another tenant's is
+    /// opened.
+    pub fn new(path: impl Fn(&str) -> PathBuf + Send + Sync + 'static, max_open: usize) -> Self {
+        Self {
+            path: Arc::new(path),
+            max_open: max_open.max(1),
+            tenants: Default::default(),
+        }
+    }
+
+    /// The pool of `tenant`'s database, failing if it isn't open and `max_open` other pools are in use
+    ///
+    /// Subscriptions to a tenant's changes keep receiving them after its pool is closed and reopened.
+    pub fn for_tenant(&self, tenant: &str) -> Result<TenantPool> {
+        let mut tenants = self.tenants.lock().unwrap();
+        let tenants = &mut *tenants;
+        let open = match tenants.open.iter().position(|open| open.tenant == tenant) {
+            Some(idx) => tenants.open.remove(idx),
+            None => {
+                if tenants.open.len() >= self.max_open {
+                    let Some(idle) = tenants.open.iter().position(|open| Arc::strong_count(&open.users) == 1) else {
+                        bail!("All {} open tenant pools are in use", self.max_open);
+                    };
+                    let closed = tenants.open.remove(idle);
+                    // Forget the closed tenant's subscribers once their streams are gone
+                    let mut subscribers = tenants.subscribers[&closed.tenant].lock().unwrap();
+                    subscribers.retain(|(_, tx)| !tx.is_closed());
+                    if subscribers.is_empty() {
+                        drop(subscribers);
+                        tenants.subscribers.remove(&closed.tenant);
+                    }
+                }
+                let subscribers = tenants.subscribers.get(tenant).cloned().unwrap_or_default();
+                let pool = Pool::open_with_subscribers((self.path)(tenant), subscribers.clone())?;
+                tenants.subscribers.insert(tenant.to_owned(), subscribers);
+                OpenPool {
+                    tenant: tenant.to_owned(),
+                    pool,
+                    users: Arc::new(()),
+                }
+            }
+        };
+        let handle = TenantPool {
+            pool: open.pool.clone(),
+            _user: open.users.clone(),
+        };
+        tenants.open.push(open);
+        Ok(handle)
+    }
+}
+
+/// A tenant's pool from [`TenantPools::for_tenant`], which isn't closed while this or its clones exist
+///
+/// Clones of the `Pool` itself aren't counted, and keep their connections open after the pool is closed.
+#[derive(Clone)]
+pub struct TenantPool {
+    pool: Pool<Sqlite>,
+    _user: Arc<()>,
+}
+
+impl Deref for TenantPool {
+    type Target = Pool<Sqlite>;
+
+    fn deref(&self) -> &Pool<Sqlite> {
+        &self.pool
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{ColumnTuple, DbColumnType, DbValue, DynColumn, Table};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Timestamp {
+    CreatedAt,
+    UpdatedAt,
+    SoftDelete,
+}
+
+/// Column types that can hold the current time, for automatic timestamp columns
+pub trait Now: DbColumnType {
+    fn now() -> Self;
+}
+
+fn since_epoch() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Seconds since the Unix epoch
+impl Now for i64 {
+    fn now() -> Self {
+        since_epoch().as_secs() as i64
+    }
+}
+
+/// Seconds since the Unix epoch
+impl Now for f64 {
+    fn now() -> Self {
+        since_epoch().as_secs_f64()
+    }
+}
+
+/// UTC time as `YYYY-MM-DD HH:MM:SS`, the format of SQLite's `CURRENT_TIMESTAMP`
+impl Now for String {
+    fn now() -> Self {
+        let secs = since_epoch().as_secs() as i64;
+        let (days, time) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+        // Howard Hinnant's civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+}
+
+impl<T: Now> Now for Option<T> {
+    fn now() -> Self {
+        Some(T::now())
+    }
+}
+
+/// The columns of `T` filled in automatically with `kind`, along with the current time
+pub(crate) fn timestamp_columns<T: Table>(
+    kind: Timestamp,
+) -> Vec<(Box<dyn DynColumn<T> + Send>, DbValue)> {
+    let mut columns = vec![];
+    T::COLUMNS.apply_columns(|col| {
+        if let Some((k, now)) = col.timestamp() {
+            if k == kind {
+                columns.push((col.boxed(), now));
+            }
+        }
+    });
+    columns
+}
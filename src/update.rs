@@ -1,17 +1,31 @@
-use std::marker::PhantomData;
+use std::{fmt, marker::PhantomData};
 
 use crate::{
-    Column, CondExpr, Database, DbColumnType, DbValue, DynColumn,
-    Pool, Table, build_condition_query, Result
+    cond_expr::{check_condition, CondExprE}, expr::{build_expr, ExprE}, Column, ColumnTuple, CondExpr, Database, DbColumnType, DbValue,
+    DynColumn, IntoExpr, Pool, PrimaryKey, Table, Timestamp, build_condition_query, timestamp_columns,
+    DbContext, QueryContext, Result
 };
 
 pub struct UpdateBuilder<'pool, Db: Database, T: Table> {
     pool: &'pool Pool<Db>,
     phantom: PhantomData<T>,
-    set: Vec<(Box<dyn DynColumn<T> + Send>, DbValue)>,
+    set: Vec<(Box<dyn DynColumn<T> + Send>, ExprE<T>)>,
     condition: CondExpr<T>,
+    expected_version: Option<DbValue>,
 }
 
+/// Returned by [`UpdateBuilder::execute`] when no row had the expected `#[sql(version)]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleVersion;
+
+impl fmt::Display for StaleVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No row with the expected version was found")
+    }
+}
+
+impl std::error::Error for StaleVersion {}
+
 impl<Db: Database> Pool<Db> {
     pub fn update<T: Table>(&self) -> UpdateBuilder<'_, Db, T> {
         UpdateBuilder {
@@ -19,6 +33,28 @@ impl<Db: Database> Pool<Db> {
             phantom: PhantomData,
             set: vec![],
             condition: CondExpr::TRUE,
+            expected_version: None,
+        }
+    }
+
+    pub fn update_row<T: PrimaryKey>(&self, row: &T) -> UpdateBuilder<'_, Db, T> {
+        let mut values = row.values().into_iter();
+        let mut set = vec![];
+        let mut expected_version = None;
+        T::COLUMNS.apply_columns(|col| {
+            let value = values.next().unwrap();
+            if col.version() {
+                expected_version = Some(value);
+            } else if !col.primary_key() {
+                set.push((col.boxed(), ExprE::Value(value)));
+            }
+        });
+        UpdateBuilder {
+            pool: self,
+            phantom: PhantomData,
+            set,
+            condition: row.primary_key(),
+            expected_version,
         }
     }
 }
@@ -26,7 +62,17 @@ impl<Db: Database> Pool<Db> {
 impl<Db: Database, T: Table> UpdateBuilder<'_, Db, T> {
     #[must_use]
     pub fn set<U: DbColumnType, V>(mut self, column: Column<T, U, V>, value: V) -> Self {
-        self.set.push((Box::new(column), column.to_db(value)));
+        self.set.push((Box::new(column), ExprE::Value(column.to_db(value))));
+        self
+    }
+
+    #[must_use]
+    pub fn set_expr<U: DbColumnType, V>(
+        mut self,
+        column: Column<T, U, V>,
+        value: impl IntoExpr<T, U>,
+    ) -> Self {
+        self.set.push((Box::new(column), value.into_expr().0));
         self
     }
 
@@ -34,41 +80,86 @@ impl<Db: Database, T: Table> UpdateBuilder<'_, Db, T> {
     pub fn r#where(self, condition: CondExpr<T>) -> Self {
         Self { condition, ..self }
     }
+
+    /// Only update rows whose `#[sql(version)]` column equals `version`, failing with [`StaleVersion`] otherwise
+    #[must_use]
+    pub fn expect_version(mut self, version: i64) -> Self {
+        self.expected_version = Some(version.to_db());
+        self
+    }
 }
 
 impl<Db: Database, T: Table> UpdateBuilder<'_, Db, T> {
-    fn build_query(self) -> (String, Vec<DbValue>) {
-        let mut next_param = {
-            let mut context = Default::default();
-            move || Db::param(&mut context)
-        };
+    /// The query, unless there's nothing to set and no version to check
+    fn build_query(mut self) -> Option<(String, Vec<DbValue>)> {
+        // Timestamps and versions only change along with other columns, or when a version check asks for it
+        if self.set.is_empty() && (self.expected_version.is_none() || version_column::<T>().is_none()) {
+            return None;
+        }
+        for (col, now) in timestamp_columns::<T>(Timestamp::UpdatedAt) {
+            self.set.retain(|(c, _)| c.name() != col.name());
+            self.set.push((col, ExprE::Value(now)));
+        }
+        let mut condition = self.condition.0;
+        if let Some(col) = version_column::<T>() {
+            self.set.retain(|(c, _)| c.name() != col.name());
+            let increment = ExprE::Binary(
+                Box::new(ExprE::Column(col.boxed())),
+                "+",
+                Box::new(ExprE::Value(1i64.to_db())),
+            );
+            self.set.push((col.boxed(), increment));
+            if let Some(expected) = self.expected_version.take() {
+                condition = CondExprE::All(vec![condition, CondExprE::ColumnEquals(col, expected)]);
+            }
+        }
+        let mut ctx = DbContext::<Db>::new(self.pool);
         let mut params = vec![];
         let set_spec = self.set
             .into_iter()
             .map(|(col, val)| {
-                params.push(val);
-                format!("\"{}\" = {}", col.name(), next_param())
+                format!(
+                    "\"{}\" = {}",
+                    col.name(),
+                    build_expr(val, Some(&col.db_type()), &mut params, &mut ctx)
+                )
             })
             .collect::<Vec<_>>()
             .join(", ");
         let cond = build_condition_query(
-            self.condition.0,
+            condition,
             &mut params,
-            &mut next_param,
+            &mut ctx,
         );
         let query = format!(
-            "UPDATE \"{}\" SET {} WHERE {}",
-            T::TABLE_NAME,
+            "UPDATE {} SET {} WHERE {}",
+            ctx.table(T::TABLE_NAME),
             set_spec,
             cond
         );
-        (query, params)
+        Some((query, params))
     }
 
     pub async fn execute(self) -> Result<()> {
-        if self.set.len() == 0 { return Ok(()) }
+        check_condition(&self.condition.0, Db::DIALECT)?;
         let pool = self.pool;
-        let (query, params) = self.build_query();
-        Db::execute(&pool.0, query, params).await
+        let versioned = self.expected_version.is_some() && version_column::<T>().is_some();
+        let Some((query, params)) = self.build_query() else {
+            return Ok(());
+        };
+        if Db::execute(&pool.0, query, params).await? == 0 && versioned {
+            return Err(StaleVersion.into());
+        }
+        Ok(())
     }
 }
+
+fn version_column<T: Table>() -> Option<Box<dyn DynColumn<T> + Send>> {
+    let mut version = None;
+    T::COLUMNS.apply_columns(|col| {
+        if col.version() {
+            version = Some(col.boxed());
+        }
+    });
+    version
+}